@@ -35,8 +35,8 @@ fn main() {
                             TelemetryData::Temps(v) => {
                                 println!("Extruder temp: {}; Bed temp: {}", v.ex_temp, v.bed_temp);
                             }
-                            TelemetryData::Progress(x) => {
-                                println!("Number of commands left in que: {}", x);
+                            TelemetryData::JobProgress(p) => {
+                                println!("Print progress: {:.1}%", p.percent);
                             }
                             TelemetryData::TargetExtruderTemp(t) => {
                                 println!("Target Extruder Temp change: {}", t);
@@ -0,0 +1,418 @@
+use crate::gcode::meatpack;
+use crate::gcode::metadata::MetadataParser;
+use crate::models::metadata::{GcodeMetadata, Thumbnail, ThumbnailFormat, ThumbnailInfo};
+use anyhow::{anyhow, bail};
+use flate2::read::ZlibDecoder;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"GCDE";
+const CHECKSUM_CRC32: u16 = 1;
+
+const BLOCK_FILE_METADATA: u16 = 0;
+const BLOCK_GCODE: u16 = 1;
+const BLOCK_SLICER_METADATA: u16 = 2;
+const BLOCK_PRINTER_METADATA: u16 = 3;
+const BLOCK_PRINT_METADATA: u16 = 4;
+const BLOCK_THUMBNAIL: u16 = 5;
+
+const COMPRESSION_NONE: u16 = 0;
+const COMPRESSION_DEFLATE: u16 = 1;
+const COMPRESSION_HEATSHRINK_11_4: u16 = 2;
+const COMPRESSION_HEATSHRINK_12_4: u16 = 3;
+
+const GCODE_ENCODING_NONE: u16 = 0;
+
+/// Content of a binary gcode file (.bgcode)
+pub struct BgcodeFile {
+    pub metadata: GcodeMetadata,
+    pub thumbnails: Vec<Thumbnail>,
+    /// decoded gcode lines
+    pub gcode: String,
+}
+
+/// the file at the given path starts with the binary gcode magic
+pub fn is_bgcode(path: impl AsRef<Path>) -> anyhow::Result<bool> {
+    let mut magic = [0; 4];
+    let mut file = File::open(path)?;
+    Ok(file.read_exact(&mut magic).is_ok() && &magic == MAGIC)
+}
+
+/// read and decode the binary gcode file at the given path
+pub fn read_bgcode(path: impl AsRef<Path>) -> anyhow::Result<BgcodeFile> {
+    parse_bgcode(&fs::read(path)?)
+}
+
+/// decode a binary gcode file
+pub fn parse_bgcode(data: &[u8]) -> anyhow::Result<BgcodeFile> {
+    let mut r = ByteReader { data, pos: 0 };
+    if r.take(4)? != MAGIC {
+        bail!("not a binary gcode file");
+    }
+    let _version = r.u32()?;
+    let checksum = r.u16()?;
+
+    let mut parser = MetadataParser::new();
+    let mut thumbnails = vec![];
+    let mut gcode = String::new();
+    while r.pos < data.len() {
+        let start = r.pos;
+        let block_type = r.u16()?;
+        let compression = r.u16()?;
+        let size = r.u32()? as usize;
+        let compressed_size = match compression {
+            COMPRESSION_NONE => size,
+            _ => r.u32()? as usize,
+        };
+        let params = r.take(if block_type == BLOCK_THUMBNAIL { 6 } else { 2 })?;
+        let payload = r.take(compressed_size)?;
+        if checksum == CHECKSUM_CRC32 {
+            let expected = r.u32()?;
+            if crc32fast::hash(&data[start..r.pos - 4]) != expected {
+                bail!("checksum mismatch in block at byte {}", start);
+            }
+        }
+
+        let content = decompress(payload, compression, size)?;
+        let encoding = u16::from_le_bytes([params[0], params[1]]);
+        match block_type {
+            BLOCK_FILE_METADATA => {
+                for (key, value) in ini_pairs(&content) {
+                    if key == "Producer" {
+                        parser.parse_line(&format!("; generated by {}", value));
+                    }
+                }
+            }
+            BLOCK_SLICER_METADATA | BLOCK_PRINTER_METADATA | BLOCK_PRINT_METADATA => {
+                for (key, value) in ini_pairs(&content) {
+                    parser.parse_line(&format!("; {} = {}", key, value));
+                }
+            }
+            BLOCK_THUMBNAIL => {
+                let param = |i: usize| u16::from_le_bytes([params[i], params[i + 1]]) as u32;
+                let format = match encoding {
+                    1 => ThumbnailFormat::Jpg,
+                    2 => ThumbnailFormat::Qoi,
+                    _ => ThumbnailFormat::Png,
+                };
+                thumbnails.push(Thumbnail {
+                    info: ThumbnailInfo {
+                        width: param(2),
+                        height: param(4),
+                        format,
+                    },
+                    data: base64(&content),
+                });
+            }
+            BLOCK_GCODE => {
+                let text = match encoding {
+                    GCODE_ENCODING_NONE => String::from_utf8_lossy(&content).to_string(),
+                    _ => meatpack::decode(&content),
+                };
+                for line in text.lines() {
+                    parser.parse_line(line);
+                }
+                gcode.push_str(&text);
+                if !gcode.ends_with('\n') {
+                    gcode.push('\n');
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut metadata = parser.finish();
+    metadata.thumbnails = thumbnails.iter().map(|t| t.info.clone()).collect();
+    Ok(BgcodeFile {
+        metadata,
+        thumbnails,
+        gcode,
+    })
+}
+
+/// open a gcode file for reading its lines, binary gcode files are decoded first
+pub(crate) fn open_gcode(path: impl AsRef<Path>) -> anyhow::Result<Box<dyn BufRead>> {
+    if is_bgcode(&path)? {
+        Ok(Box::new(Cursor::new(read_bgcode(path)?.gcode)))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, n: usize) -> anyhow::Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + n)
+            .ok_or_else(|| anyhow!("unexpected end of file at byte {}", self.pos))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+}
+
+fn decompress(payload: &[u8], compression: u16, size: usize) -> anyhow::Result<Vec<u8>> {
+    let content = match compression {
+        COMPRESSION_NONE => payload.to_vec(),
+        COMPRESSION_DEFLATE => {
+            let mut content = Vec::with_capacity(size);
+            ZlibDecoder::new(payload).read_to_end(&mut content)?;
+            content
+        }
+        COMPRESSION_HEATSHRINK_11_4 => heatshrink(payload, 11, 4, size),
+        COMPRESSION_HEATSHRINK_12_4 => heatshrink(payload, 12, 4, size),
+        c => bail!("unknown compression {}", c),
+    };
+    if content.len() != size {
+        bail!("block size {} does not match {}", content.len(), size);
+    }
+    Ok(content)
+}
+
+/// Heatshrink (LZSS) decoder with window size 2^window and back references up to 2^lookahead bytes
+fn heatshrink(input: &[u8], window: u32, lookahead: u32, size: usize) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(size);
+    let mut bit_pos = 0;
+    let mut bits = |n: u32| -> Option<usize> {
+        let mut v = 0;
+        for _ in 0..n {
+            let byte = input.get(bit_pos / 8)?;
+            v = (v << 1) | ((byte >> (7 - bit_pos % 8)) & 1) as usize;
+            bit_pos += 1;
+        }
+        Some(v)
+    };
+
+    while out.len() < size {
+        match bits(1) {
+            Some(1) => match bits(8) {
+                Some(b) => out.push(b as u8),
+                None => break,
+            },
+            Some(_) => {
+                let (Some(index), Some(count)) = (bits(window), bits(lookahead)) else {
+                    break;
+                };
+                // the window starts zero filled, references before the output read 0
+                for _ in 0..=count {
+                    let b = out.len().checked_sub(index + 1).map_or(0, |i| out[i]);
+                    out.push(b);
+                }
+            }
+            None => break,
+        }
+    }
+    out
+}
+
+/// `key=value` lines of a metadata block
+fn ini_pairs(content: &[u8]) -> Vec<(String, String)> {
+    String::from_utf8_lossy(content)
+        .lines()
+        .filter_map(|l| l.split_once('='))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect()
+}
+
+fn base64(data: &[u8]) -> String {
+    const CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(CHARS[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    /// heatshrink bit stream of literals and back references (offset, count)
+    #[derive(Default)]
+    struct BitWriter {
+        bytes: Vec<u8>,
+        bits: usize,
+    }
+
+    impl BitWriter {
+        fn push(&mut self, v: usize, n: u32) {
+            for i in (0..n).rev() {
+                if self.bits % 8 == 0 {
+                    self.bytes.push(0);
+                }
+                let bit = ((v >> i) & 1) as u8;
+                *self.bytes.last_mut().unwrap() |= bit << (7 - self.bits % 8);
+                self.bits += 1;
+            }
+        }
+
+        fn literal(&mut self, b: u8) {
+            self.push(1, 1);
+            self.push(b as usize, 8);
+        }
+
+        fn backref(&mut self, window: u32, offset: usize, count: usize) {
+            self.push(0, 1);
+            self.push(offset - 1, window);
+            self.push(count - 1, 4);
+        }
+    }
+
+    #[test]
+    fn heatshrink_literals_and_references() {
+        let mut w = BitWriter::default();
+        for b in b"G1 X" {
+            w.literal(*b);
+        }
+        w.literal(b'1');
+        w.literal(b'\n');
+        // "G1 X1\n" again
+        w.backref(11, 6, 6);
+        // overlapping reference repeats the last byte
+        w.literal(b'0');
+        w.backref(11, 1, 3);
+        assert_eq!(heatshrink(&w.bytes, 11, 4, 16), b"G1 X1\nG1 X1\n0000");
+    }
+
+    #[test]
+    fn heatshrink_reference_before_the_output_reads_zeros() {
+        let mut w = BitWriter::default();
+        w.literal(b'a');
+        w.backref(12, 3, 4);
+        assert_eq!(heatshrink(&w.bytes, 12, 4, 5), [b'a', 0, 0, b'a', 0]);
+    }
+
+    #[test]
+    fn base64_known_vectors() {
+        // RFC 4648
+        for (plain, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(base64(plain.as_bytes()), encoded);
+        }
+    }
+
+    fn block(block_type: u16, compression: u16, params: &[u8], content: &[u8]) -> Vec<u8> {
+        let payload = match compression {
+            COMPRESSION_DEFLATE => {
+                let mut e = ZlibEncoder::new(vec![], Compression::default());
+                e.write_all(content).unwrap();
+                e.finish().unwrap()
+            }
+            _ => content.to_vec(),
+        };
+        let mut b = vec![];
+        b.extend(block_type.to_le_bytes());
+        b.extend(compression.to_le_bytes());
+        b.extend((content.len() as u32).to_le_bytes());
+        if compression != COMPRESSION_NONE {
+            b.extend((payload.len() as u32).to_le_bytes());
+        }
+        b.extend(params);
+        b.extend(payload);
+        b.extend(crc32fast::hash(&b).to_le_bytes());
+        b
+    }
+
+    fn file(blocks: &[Vec<u8>]) -> Vec<u8> {
+        let mut f = MAGIC.to_vec();
+        f.extend(1u32.to_le_bytes());
+        f.extend(CHECKSUM_CRC32.to_le_bytes());
+        for b in blocks {
+            f.extend(b);
+        }
+        f
+    }
+
+    fn thumbnail_params() -> Vec<u8> {
+        [0u16, 16, 12]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn parse_blocks() {
+        let data = file(&[
+            block(
+                BLOCK_FILE_METADATA,
+                COMPRESSION_NONE,
+                &[0, 0],
+                b"Producer=PrusaSlicer 2.6.0\n",
+            ),
+            block(
+                BLOCK_PRINT_METADATA,
+                COMPRESSION_DEFLATE,
+                &[0, 0],
+                b"layer_height=0.2\n",
+            ),
+            block(
+                BLOCK_THUMBNAIL,
+                COMPRESSION_NONE,
+                &thumbnail_params(),
+                b"foo",
+            ),
+            block(BLOCK_GCODE, COMPRESSION_DEFLATE, &[0, 0], b"G28\nG1 X10"),
+            block(BLOCK_GCODE, COMPRESSION_NONE, &[0, 0], b"M84\n"),
+        ]);
+
+        let bgcode = parse_bgcode(&data).unwrap();
+        assert_eq!(bgcode.gcode, "G28\nG1 X10\nM84\n");
+        assert_eq!(bgcode.metadata.slicer_version.as_deref(), Some("2.6.0"));
+        assert_eq!(bgcode.metadata.layer_height, Some(0.2));
+        assert_eq!(bgcode.thumbnails.len(), 1);
+        assert_eq!(bgcode.thumbnails[0].data, "Zm9v");
+        assert_eq!(bgcode.thumbnails[0].info.width, 16);
+        assert_eq!(bgcode.thumbnails[0].info.height, 12);
+        assert_eq!(bgcode.metadata.thumbnails.len(), 1);
+    }
+
+    #[test]
+    fn corrupted_block_is_rejected() {
+        let mut data = file(&[block(BLOCK_GCODE, COMPRESSION_NONE, &[0, 0], b"G28\n")]);
+        let i = data.len() - 6;
+        data[i] ^= 1;
+        assert!(parse_bgcode(&data).is_err());
+    }
+
+    #[test]
+    fn truncated_file_is_rejected() {
+        let data = file(&[block(BLOCK_GCODE, COMPRESSION_NONE, &[0, 0], b"G28\n")]);
+        assert!(parse_bgcode(&data[..data.len() - 2]).is_err());
+        assert!(parse_bgcode(b"G28\n").is_err());
+    }
+}
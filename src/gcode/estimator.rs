@@ -0,0 +1,460 @@
+use crate::gcode::bgcode::open_gcode;
+use crate::models::profile::MotionLimits;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::io::BufRead;
+use std::path::Path;
+
+lazy_static! {
+    static ref RE_CMD: Regex = Regex::new(r"^([GM]\d+)").unwrap();
+    static ref RE_PARAM: Regex = Regex::new(r"([A-Z])\s*(-?\d*\.?\d+)").unwrap();
+}
+
+const X: usize = 0;
+const Y: usize = 1;
+const Z: usize = 2;
+const E: usize = 3;
+
+/// moves the planner looks ahead, marlin's BLOCK_BUFFER_SIZE
+const PLANNER_BLOCKS: usize = 16;
+/// length of the segments arcs are split into in mm, marlin's MM_PER_ARC_SEGMENT
+const ARC_SEGMENT_LENGTH: f32 = 1.0;
+
+/// A linear move of the printer
+#[derive(Clone, Copy)]
+struct Block {
+    length: f32,
+    /// direction of the move per axis
+    unit: [f32; 4],
+    /// target speed in mm/s
+    nominal: f32,
+    /// acceleration in mm/s²
+    accel: f32,
+    /// junction speed with the previous move
+    max_entry: f32,
+    /// planned entry speed
+    entry: f32,
+}
+
+/// Estimates the print time of gcode lines by simulating the planner of the firmware
+/// Moves are accelerated with trapezoid profiles, junction speeds use junction deviation or jerk.
+/// Like the firmware the planner looks ahead a few moves to slow down in time before sharp corners
+pub struct TimeEstimator {
+    limits: MotionLimits,
+    pos: [f32; 4],
+    relative_moves: bool,
+    relative_extrusion: bool,
+    /// feedrate in mm/s
+    feedrate: f32,
+    speed_factor: f32,
+    /// moves waiting for the following ones to know their exit speed
+    planned: VecDeque<Block>,
+    /// time of the moves which left the planner
+    total: f64,
+}
+
+impl TimeEstimator {
+    pub fn new(limits: MotionLimits) -> Self {
+        TimeEstimator {
+            limits,
+            pos: [0.0; 4],
+            relative_moves: false,
+            relative_extrusion: false,
+            feedrate: 25.0,
+            speed_factor: 1.0,
+            planned: VecDeque::new(),
+            total: 0.0,
+        }
+    }
+
+    /// simulate a gcode line without comments and return the estimated time in seconds
+    /// of all moves up to this line, the moves in the planner are counted as if the printer stops after them
+    pub fn add_line(&mut self, line: &str) -> f32 {
+        let line = line.trim();
+        let cmd = match RE_CMD.captures(line) {
+            None => return self.elapsed(),
+            Some(c) => c[1].to_string(),
+        };
+        let params: Vec<(char, f32)> = RE_PARAM
+            .captures_iter(&line[cmd.len()..])
+            .filter_map(|c| Some((c[1].chars().next()?, c[2].parse().ok()?)))
+            .collect();
+        let param = |p: char| params.iter().find(|(k, _)| *k == p).map(|(_, v)| *v);
+
+        match cmd.as_str() {
+            "G0" | "G1" => self.linear_move(&param),
+            "G2" | "G3" => self.arc_move(&param, cmd == "G2"),
+            "G4" => {
+                self.flush();
+                let secs = param('S').unwrap_or(0.0) + param('P').unwrap_or(0.0) / 1000.0;
+                self.total += secs as f64;
+            }
+            "G28" => {
+                self.flush();
+                self.pos = [0.0, 0.0, 0.0, self.pos[E]];
+            }
+            "G90" => {
+                self.relative_moves = false;
+                self.relative_extrusion = false;
+            }
+            "G91" => {
+                self.relative_moves = true;
+                self.relative_extrusion = true;
+            }
+            "M82" => self.relative_extrusion = false,
+            "M83" => self.relative_extrusion = true,
+            "G92" => {
+                for (i, a) in ['X', 'Y', 'Z', 'E'].iter().enumerate() {
+                    if let Some(v) = param(*a) {
+                        self.pos[i] = v;
+                    }
+                }
+            }
+            "M201" => Self::set_axes(&mut self.limits.max_acceleration, &param),
+            "M203" => Self::set_axes(&mut self.limits.max_feedrate, &param),
+            "M204" => {
+                if let Some(s) = param('S') {
+                    self.limits.print_acceleration = s;
+                    self.limits.travel_acceleration = s;
+                }
+                if let Some(p) = param('P') {
+                    self.limits.print_acceleration = p;
+                }
+                if let Some(r) = param('R') {
+                    self.limits.retract_acceleration = r;
+                }
+                if let Some(t) = param('T') {
+                    self.limits.travel_acceleration = t;
+                }
+            }
+            "M205" => {
+                Self::set_axes(&mut self.limits.jerk, &param);
+                if let Some(j) = param('J') {
+                    self.limits.junction_deviation = Some(j);
+                }
+            }
+            "M220" => {
+                if let Some(s) = param('S') {
+                    self.speed_factor = s / 100.0;
+                }
+            }
+            // wait for the planner to run empty
+            "M400" | "M109" | "M190" => self.flush(),
+            _ => {}
+        }
+        self.elapsed()
+    }
+
+    fn elapsed(&self) -> f32 {
+        (self.total + self.planned_time()) as f32
+    }
+
+    /// time of the moves in the planner if the printer stops after the last one
+    fn planned_time(&self) -> f64 {
+        (0..self.planned.len())
+            .map(|i| {
+                let exit = self.planned.get(i + 1).map_or(0.0, |b| b.entry);
+                Self::block_time(&self.planned[i], self.planned[i].entry, exit) as f64
+            })
+            .sum()
+    }
+
+    /// finish the simulation and return the estimated time in seconds
+    pub fn finish(mut self) -> f32 {
+        self.flush();
+        self.total as f32
+    }
+
+    fn set_axes(values: &mut [f32; 4], param: &dyn Fn(char) -> Option<f32>) {
+        for (i, a) in ['X', 'Y', 'Z', 'E'].iter().enumerate() {
+            if let Some(v) = param(*a) {
+                values[i] = v;
+            }
+        }
+    }
+
+    fn target(&mut self, param: &dyn Fn(char) -> Option<f32>) -> [f32; 4] {
+        if let Some(f) = param('F') {
+            self.feedrate = f / 60.0;
+        }
+
+        let mut target = self.pos;
+        for (i, a) in ['X', 'Y', 'Z', 'E'].iter().enumerate() {
+            if let Some(v) = param(*a) {
+                let relative = if i == E {
+                    self.relative_extrusion
+                } else {
+                    self.relative_moves
+                };
+                target[i] = if relative { self.pos[i] + v } else { v };
+            }
+        }
+        target
+    }
+
+    fn linear_move(&mut self, param: &dyn Fn(char) -> Option<f32>) {
+        let target = self.target(param);
+        let mut delta = [0.0; 4];
+        for i in 0..4 {
+            delta[i] = target[i] - self.pos[i];
+        }
+        self.pos = target;
+
+        let xyz = (delta[X] * delta[X] + delta[Y] * delta[Y] + delta[Z] * delta[Z]).sqrt();
+        let length = if xyz > 0.0 { xyz } else { delta[E].abs() };
+        if length < 1e-6 {
+            return;
+        }
+        self.add_block(delta, length);
+    }
+
+    fn arc_move(&mut self, param: &dyn Fn(char) -> Option<f32>, clockwise: bool) {
+        let start = self.pos;
+        let target = self.target(param);
+        let (i, j) = (param('I').unwrap_or(0.0), param('J').unwrap_or(0.0));
+
+        let center = [start[X] + i, start[Y] + j];
+        let radius = (i * i + j * j).sqrt();
+        let (sx, sy) = (start[X] - center[0], start[Y] - center[1]);
+        let (ex, ey) = (target[X] - center[0], target[Y] - center[1]);
+        let mut angle = (sx * ey - sy * ex).atan2(sx * ex + sy * ey);
+        if clockwise && angle >= 0.0 {
+            angle -= 2.0 * PI;
+        } else if !clockwise && angle <= 0.0 {
+            angle += 2.0 * PI;
+        }
+
+        self.pos = target;
+
+        let planar = angle.abs() * radius;
+        let length = (planar * planar + (target[Z] - start[Z]).powi(2)).sqrt();
+        if length < 1e-6 {
+            return;
+        }
+        // the firmware splits arcs into short linear moves
+        let segments = (planar / ARC_SEGMENT_LENGTH).floor().max(1.0);
+        let mut prev = start;
+        for n in 1..=segments as u32 {
+            let f = n as f32 / segments;
+            let (sin, cos) = (angle * f).sin_cos();
+            let mut point = [0.0; 4];
+            point[X] = center[0] + sx * cos - sy * sin;
+            point[Y] = center[1] + sx * sin + sy * cos;
+            for a in [Z, E] {
+                point[a] = start[a] + (target[a] - start[a]) * f;
+            }
+            if n == segments as u32 {
+                point = target;
+            }
+
+            let delta: [f32; 4] = std::array::from_fn(|a| point[a] - prev[a]);
+            let xyz = (delta[X] * delta[X] + delta[Y] * delta[Y] + delta[Z] * delta[Z]).sqrt();
+            if xyz > 1e-6 {
+                self.add_block(delta, xyz);
+            }
+            prev = point;
+        }
+    }
+
+    fn add_block(&mut self, delta: [f32; 4], length: f32) {
+        let unit = delta.map(|d| d / length);
+
+        let extrude_only = delta[X] == 0.0 && delta[Y] == 0.0 && delta[Z] == 0.0;
+        let mut accel = if extrude_only {
+            self.limits.retract_acceleration
+        } else if delta[E] > 0.0 {
+            self.limits.print_acceleration
+        } else {
+            self.limits.travel_acceleration
+        };
+        let mut nominal = self.feedrate * self.speed_factor;
+        for (i, u) in unit.iter().map(|u| u.abs()).enumerate() {
+            if u > 1e-6 {
+                nominal = nominal.min(self.limits.max_feedrate[i] / u);
+                accel = accel.min(self.limits.max_acceleration[i] / u);
+            }
+        }
+
+        let mut block = Block {
+            length,
+            unit,
+            nominal: nominal.max(0.1),
+            accel: accel.max(1.0),
+            max_entry: 0.0,
+            entry: 0.0,
+        };
+        if let Some(prev) = self.planned.back() {
+            block.max_entry = self.junction_speed(prev, &block);
+        }
+        self.planned.push_back(block);
+        self.plan();
+
+        // the oldest move is executed with the speeds planned for it
+        if self.planned.len() > PLANNER_BLOCKS {
+            let block = self.planned.pop_front().unwrap();
+            let exit = self.planned[0].entry;
+            self.total += Self::block_time(&block, block.entry, exit) as f64;
+        }
+    }
+
+    /// plan the entry speeds of the moves, the printer has to be able to stop after the last one
+    fn plan(&mut self) {
+        // slow down in time for the following junctions, the first entry is already executed
+        let mut next_entry = 0.0;
+        for block in self.planned.iter_mut().skip(1).rev() {
+            let reachable = (next_entry * next_entry + 2.0 * block.accel * block.length).sqrt();
+            block.entry = block.max_entry.min(reachable);
+            next_entry = block.entry;
+        }
+        // the speed up on the previous move limits the entry speeds
+        for i in 1..self.planned.len() {
+            let prev = self.planned[i - 1];
+            let reachable = (prev.entry * prev.entry + 2.0 * prev.accel * prev.length).sqrt();
+            let block = &mut self.planned[i];
+            block.entry = block.entry.min(reachable);
+        }
+    }
+
+    /// finish the planned moves with a full stop
+    fn flush(&mut self) {
+        self.total += self.planned_time();
+        self.planned.clear();
+    }
+
+    fn junction_speed(&self, prev: &Block, next: &Block) -> f32 {
+        let max = prev.nominal.min(next.nominal);
+        match self.limits.junction_deviation {
+            Some(jd) => {
+                let cos_theta = -(0..4).map(|i| prev.unit[i] * next.unit[i]).sum::<f32>();
+                if cos_theta > 0.999_999 {
+                    // direction reversal
+                    return 0.0;
+                }
+                if cos_theta < -0.999_999 {
+                    // straight line
+                    return max;
+                }
+                let sin_theta_d2 = (0.5 * (1.0 - cos_theta)).sqrt();
+                let v2 = next.accel * jd * sin_theta_d2 / (1.0 - sin_theta_d2);
+                v2.sqrt().min(max)
+            }
+            None => {
+                let mut v = max;
+                for i in 0..4 {
+                    let du = (prev.unit[i] - next.unit[i]).abs();
+                    if du > 1e-6 {
+                        v = v.min(self.limits.jerk[i] / du);
+                    }
+                }
+                v
+            }
+        }
+    }
+
+    /// time of a move with trapezoid speed profile
+    fn block_time(block: &Block, entry: f32, exit: f32) -> f32 {
+        let (d, a, v) = (block.length, block.accel, block.nominal);
+        let entry = entry.min(v);
+        let exit = exit.min(v);
+
+        let accel_dist = (v * v - entry * entry) / (2.0 * a);
+        let decel_dist = (v * v - exit * exit) / (2.0 * a);
+        if accel_dist + decel_dist <= d {
+            (v - entry) / a + (v - exit) / a + (d - accel_dist - decel_dist) / v
+        } else {
+            // nominal speed is never reached
+            let peak = ((2.0 * a * d + entry * entry + exit * exit) / 2.0).sqrt();
+            (peak - entry) / a + (peak - exit) / a
+        }
+    }
+}
+
+/// estimate the print time in seconds of the gcode file at the given path
+pub fn estimate_file(path: impl AsRef<Path>, limits: MotionLimits) -> anyhow::Result<f32> {
+    let mut estimator = TimeEstimator::new(limits);
+    for line in open_gcode(path)?.lines() {
+        let line = line?;
+        estimator.add_line(line.split(';').next().unwrap_or(""));
+    }
+    Ok(estimator.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 100 mm/s and 1000 mm/s² on all axes, corners with direction reversals are full stops
+    fn estimate(lines: &[&str]) -> f32 {
+        let mut estimator = TimeEstimator::new(MotionLimits {
+            max_feedrate: [1000.0; 4],
+            max_acceleration: [1000.0; 4],
+            print_acceleration: 1000.0,
+            retract_acceleration: 1000.0,
+            travel_acceleration: 1000.0,
+            jerk: [0.0; 4],
+            junction_deviation: None,
+        });
+        estimator.add_line("G1 F6000");
+        for line in lines {
+            estimator.add_line(line);
+        }
+        estimator.finish()
+    }
+
+    fn assert_time(time: f32, expected: f32) {
+        assert!((time - expected).abs() < 1e-3, "{} != {}", time, expected);
+    }
+
+    #[test]
+    fn trapezoid() {
+        // 0.1 s acceleration and deceleration over 5 mm each, 90 mm at 100 mm/s
+        assert_time(estimate(&["G1 X100"]), 1.1);
+        // nominal speed is never reached: triangle with a peak of sqrt(1000 * 4) mm/s
+        assert_time(estimate(&["G1 X4"]), 2.0 * 4000f32.sqrt() / 1000.0);
+    }
+
+    #[test]
+    fn straight_moves_keep_their_speed() {
+        let lines: Vec<String> = (1..=10).map(|i| format!("G1 X{}", i * 10)).collect();
+        let lines: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+        assert_time(estimate(&lines), 1.1);
+    }
+
+    #[test]
+    fn slow_down_before_a_reversal() {
+        // 10 mm in short moves reach exactly 100 mm/s and have to stop before the reversal
+        let mut lines: Vec<String> = (1..=10).map(|i| format!("G1 X{}", i)).collect();
+        lines.push("G1 X0".to_string());
+        let lines: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+        assert_time(estimate(&lines), 0.4);
+    }
+
+    #[test]
+    fn look_ahead_is_limited_to_the_planner() {
+        // 0.1 mm moves: the printer has to be able to stop within 1.6 mm, below sqrt(2 * 1000 * 1.6) mm/s
+        let mut lines: Vec<String> = (1..=400)
+            .map(|i| format!("G1 X{}", i as f32 / 10.0))
+            .collect();
+        lines.push("G1 X0".to_string());
+        let lines: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+        assert!(estimate(&lines) > 40.0 / 3200f32.sqrt() + 0.5);
+    }
+
+    #[test]
+    fn dwell_and_line_times() {
+        let mut estimator = TimeEstimator::new(MotionLimits::default());
+        estimator.add_line("G4 S2");
+        assert_time(estimator.add_line("G4 P500"), 2.5);
+        assert_time(estimator.finish(), 2.5);
+    }
+
+    #[test]
+    fn arcs_are_segmented() {
+        // without jerk every segment of the half circle stops, 31 chords of the 10 mm radius
+        let chord = 20.0 * (PI / 62.0).sin();
+        let time = estimate(&["G1 X10", "G4 P0", "G3 X-10 Y0 I-10 J0"]);
+        assert_time(time, 0.2 + 31.0 * 2.0 * (chord / 1000.0).sqrt());
+    }
+}
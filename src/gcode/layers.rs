@@ -0,0 +1,154 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    // ;LAYER_CHANGE (PrusaSlicer/OrcaSlicer), ;LAYER:3 (Cura/ideaMaker), ; layer 3, Z = 0.8 (Simplify3D)
+    static ref RE_LAYER_COMMENT: Regex = Regex::new(r"^;\s*(LAYER_CHANGE|LAYER:\s*-?\d+|layer \d+)").unwrap();
+    // ;Z:0.8
+    static ref RE_Z_COMMENT: Regex = Regex::new(r"^;\s*Z:\s*([\d.]+)").unwrap();
+    static ref RE_MOVE_Z: Regex = Regex::new(r"^G[01]\s.*Z([\d.]+)").unwrap();
+    static ref RE_EXTRUDING_MOVE: Regex = Regex::new(r"^G[0-3]\s.*E(-?[\d.]+)").unwrap();
+    // G92 E0 / G92 E5.2
+    static ref RE_E_RESET: Regex = Regex::new(r"^G92\b.*E(-?[\d.]*)").unwrap();
+    static ref RE_EXTRUSION_MODE: Regex = Regex::new(r"^(G9[01]|M8[23])\b").unwrap();
+}
+
+/// Finds the layer changes of a gcode file
+/// Slicer layer comments are preferred, files without them fall back to z increases of extruding moves.
+/// A layer starts at the move to its height
+#[derive(Default)]
+pub(crate) struct LayerDetector {
+    z: f32,
+    /// position of the last move which changed z
+    z_index: usize,
+    /// position of the first z move after the layer comment
+    comment_z_index: Option<usize>,
+    e: f32,
+    relative_e: bool,
+    comment_pending: bool,
+    comment_z: Option<f32>,
+    comment_layers: Vec<(usize, f32)>,
+    z_layers: Vec<(usize, f32)>,
+}
+
+impl LayerDetector {
+    /// parse a line of the file, `index` is the position the next command is queued at
+    pub(crate) fn parse_line(&mut self, line: &str, index: usize) {
+        let line = line.trim();
+        if line.starts_with(';') {
+            if RE_LAYER_COMMENT.is_match(line) {
+                self.comment_pending = true;
+                self.comment_z = None;
+                self.comment_z_index = None;
+            } else if let Some(c) = RE_Z_COMMENT.captures(line) {
+                self.comment_z = c[1].parse().ok();
+            }
+            return;
+        }
+
+        if let Some(c) = RE_MOVE_Z.captures(line) {
+            self.z = c[1].parse().unwrap_or(self.z);
+            self.z_index = index;
+            if self.comment_pending && self.comment_z_index.is_none() {
+                self.comment_z_index = Some(index);
+            }
+        }
+        if let Some(c) = RE_E_RESET.captures(line) {
+            self.e = c[1].parse().unwrap_or(0.0);
+        }
+        if let Some(c) = RE_EXTRUSION_MODE.captures(line) {
+            self.relative_e = matches!(&c[1], "G91" | "M83");
+        }
+
+        // a layer is detected with its first extruding move
+        if let Some(c) = RE_EXTRUDING_MOVE.captures(line) {
+            let e: f32 = c[1].parse().unwrap_or(0.0);
+            let extruding = if self.relative_e { e > 0.0 } else { e > self.e };
+            self.e = e;
+            if !extruding {
+                return;
+            }
+
+            if self.comment_pending {
+                self.comment_pending = false;
+                let z = self.comment_z.unwrap_or(self.z);
+                self.comment_layers
+                    .push((self.comment_z_index.unwrap_or(index), z));
+            }
+            if self.z_layers.last().is_none_or(|(_, z)| self.z > *z) {
+                self.z_layers.push((self.z_index, self.z));
+            }
+        }
+    }
+
+    /// positions and z heights of the detected layers
+    pub(crate) fn finish(self) -> Vec<(usize, f32)> {
+        if self.comment_layers.is_empty() {
+            self.z_layers
+        } else {
+            self.comment_layers
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layers(lines: &[&str]) -> Vec<(usize, f32)> {
+        let mut detector = LayerDetector::default();
+        for (i, line) in lines.iter().enumerate() {
+            detector.parse_line(line, i);
+        }
+        detector.finish()
+    }
+
+    #[test]
+    fn comment_layers_start_at_the_z_move() {
+        let l = layers(&[
+            ";LAYER_CHANGE",
+            ";Z:0.2",
+            "G1 E-0.8 F2100",
+            "G1 Z0.2 F720",
+            "G1 X10 Y10",
+            "G1 E0.8",
+            "G1 X20 Y10 E1.5",
+            ";LAYER_CHANGE",
+            ";Z:0.4",
+            "G1 Z0.4",
+            "G1 X10 Y10 E2",
+        ]);
+        assert_eq!(l, vec![(3, 0.2), (9, 0.4)]);
+    }
+
+    #[test]
+    fn z_layers_start_at_the_z_move() {
+        let l = layers(&[
+            "G28",
+            "G1 Z0.3 F720",
+            "G1 X10 Y10",
+            "G1 X20 Y10 E1",
+            // travel with z hop
+            "G1 Z0.9",
+            "G1 X30",
+            "G1 Z0.6",
+            "G1 X40 E2",
+        ]);
+        assert_eq!(l, vec![(1, 0.3), (6, 0.6)]);
+    }
+
+    #[test]
+    fn extruder_reset_to_a_value() {
+        let l = layers(&[
+            "G1 Z0.2",
+            "G1 X10 E1",
+            "G92 E5",
+            "G1 Z0.4",
+            // no extrusion relative to the reset position
+            "G1 X20 E5",
+            "G1 Z0.6",
+            "G1 X30 E5.5",
+        ]);
+        assert_eq!(l, vec![(0, 0.2), (5, 0.6)]);
+    }
+}
@@ -0,0 +1,201 @@
+/// a full width character follows instead of a packed one
+const FULL_WIDTH: u8 = 0b1111;
+/// two signal bytes introduce a command byte
+const SIGNAL: u8 = 0xFF;
+const CMD_ENABLE_PACKING: u8 = 0xFB;
+const CMD_DISABLE_PACKING: u8 = 0xFA;
+const CMD_RESET: u8 = 0xF9;
+const CMD_ENABLE_NO_SPACES: u8 = 0xF7;
+const CMD_DISABLE_NO_SPACES: u8 = 0xF6;
+
+/// characters of the 4 bit codes, in no spaces mode the space is replaced by 'E'
+const PACKED_CHARS: [u8; 15] = *b"0123456789. \nGX";
+
+fn unpack(code: u8, no_spaces: bool) -> u8 {
+    match PACKED_CHARS[code as usize] {
+        b' ' if no_spaces => b'E',
+        c => c,
+    }
+}
+
+/// decode MeatPack packed gcode, spaces removed by the no spaces mode are added again to G commands
+pub fn decode(data: &[u8]) -> String {
+    let mut out: Vec<u8> = Vec::with_capacity(data.len() * 2);
+    let mut packing = false;
+    let mut no_spaces = false;
+
+    let mut i = 0;
+    while i < data.len() {
+        let b = data[i];
+        i += 1;
+
+        if b == SIGNAL && data.get(i) == Some(&SIGNAL) {
+            match data.get(i + 1) {
+                Some(&CMD_ENABLE_PACKING) => packing = true,
+                Some(&CMD_DISABLE_PACKING) => packing = false,
+                Some(&CMD_ENABLE_NO_SPACES) => no_spaces = true,
+                Some(&CMD_DISABLE_NO_SPACES) => no_spaces = false,
+                Some(&CMD_RESET) => {
+                    packing = false;
+                    no_spaces = false;
+                }
+                _ => {}
+            }
+            i += 2;
+            continue;
+        }
+        if !packing {
+            out.push(b);
+            continue;
+        }
+
+        // the low nibble is the first character, full width characters follow in order
+        for code in [b & 0x0F, b >> 4] {
+            if code == FULL_WIDTH {
+                if let Some(c) = data.get(i) {
+                    out.push(*c);
+                }
+                i += 1;
+            } else {
+                out.push(unpack(code, no_spaces));
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out)
+        .lines()
+        .map(|l| add_spaces(l.trim()))
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// `G1X10Y5` -> `G1 X10 Y5`, comments and message texts are kept as they are
+fn add_spaces(line: &str) -> String {
+    let has_params = line.starts_with('G') || line.starts_with('M');
+    if !has_params || line.starts_with("M117") || line.starts_with("M118") {
+        return line.to_string();
+    }
+    let (cmd, comment) = match line.find(';') {
+        Some(i) => line.split_at(i),
+        None => (line, ""),
+    };
+
+    let mut result = String::with_capacity(line.len() + 8);
+    for (i, c) in cmd.chars().enumerate() {
+        if i > 0 && c.is_ascii_uppercase() && !result.ends_with(' ') {
+            result.push(' ');
+        }
+        result.push(c);
+    }
+    result.push_str(comment);
+    result
+}
+
+/// commands with text arguments whose spaces must be kept
+const TEXT_COMMANDS: [&str; 7] = ["M23", "M28", "M30", "M32", "M117", "M118", "M928"];
+
+/// signal bytes which query the MeatPack state of the firmware
+pub const QUERY_STATE: [u8; 3] = [SIGNAL, SIGNAL, 0xF8];
+/// signal bytes which enable packing and the no spaces mode
+pub const ENABLE: [u8; 6] = [
+    SIGNAL,
+    SIGNAL,
+    CMD_ENABLE_PACKING,
+    SIGNAL,
+    SIGNAL,
+    CMD_ENABLE_NO_SPACES,
+];
+/// signal bytes which disable packing
+pub const DISABLE: [u8; 3] = [SIGNAL, SIGNAL, CMD_DISABLE_PACKING];
+
+fn pack(c: u8, no_spaces: bool) -> Option<u8> {
+    match c {
+        b'E' if no_spaces => Some(0b1011),
+        b' ' if no_spaces => None,
+        c => PACKED_CHARS.iter().position(|p| *p == c).map(|p| p as u8),
+    }
+}
+
+/// encode a gcode line without comment for a firmware with enabled packing and no spaces mode
+pub fn encode_line(line: &str) -> Vec<u8> {
+    let keep_spaces = TEXT_COMMANDS
+        .iter()
+        .any(|c| line.split_whitespace().next() == Some(*c));
+    let mut chars: Vec<u8> = line.bytes().filter(|c| keep_spaces || *c != b' ').collect();
+    // characters are packed in pairs, a trailing space is ignored by the firmware
+    if chars.len() % 2 == 0 {
+        chars.push(b' ');
+    }
+    chars.push(b'\n');
+
+    let mut out = Vec::with_capacity(chars.len());
+    for pair in chars.chunks(2) {
+        let (first, second) = (pair[0], pair[1]);
+        let (a, b) = (pack(first, true), pack(second, true));
+        out.push(a.unwrap_or(FULL_WIDTH) | (b.unwrap_or(FULL_WIDTH) << 4));
+        if a.is_none() {
+            out.push(first);
+        }
+        if b.is_none() {
+            out.push(second);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(line: &str) -> String {
+        let mut data = ENABLE.to_vec();
+        data.extend(encode_line(line));
+        decode(&data)
+    }
+
+    #[test]
+    fn known_vectors() {
+        // G=0b1101 2=0b0010, 8=0b1000 \n=0b1100
+        assert_eq!(encode_line("G28"), [0x2D, 0xC8]);
+        // M is sent full width after the packed byte
+        assert_eq!(encode_line("M84"), [0x8F, b'M', 0xC4]);
+        // even length, padded with a full width space
+        assert_eq!(encode_line("G1"), [0x1D, 0xCF, b' ']);
+    }
+
+    #[test]
+    fn round_trip_odd_and_even_lengths() {
+        for line in ["G28", "G1", "G1 X1", "G1 X10.5 Y-3", "M104 S210", "M84"] {
+            assert_eq!(round_trip(line), line);
+        }
+    }
+
+    #[test]
+    fn no_spaces_mode_packs_e() {
+        let encoded = encode_line("G1 E0.5 F1800");
+        // the spaces are removed and E is packed
+        assert!(!encoded.contains(&b' ') && !encoded.contains(&b'E'));
+        assert_eq!(round_trip("G1 E0.5 F1800"), "G1 E0.5 F1800");
+        assert_eq!(round_trip("G92 E0"), "G92 E0");
+    }
+
+    #[test]
+    fn unpackable_characters() {
+        assert_eq!(round_trip("T1"), "T1");
+        assert_eq!(round_trip("M106 P1 S255"), "M106 P1 S255");
+        // text commands keep their spaces, two full width characters in one byte
+        assert_eq!(round_trip("M117 Hi there"), "M117 Hi there");
+        assert_eq!(round_trip("M23 file name.gco"), "M23 file name.gco");
+    }
+
+    #[test]
+    fn decode_without_packing() {
+        assert_eq!(decode(b"G28\nG1 X1\n"), "G28\nG1 X1");
+        let mut data = ENABLE.to_vec();
+        data.extend(encode_line("G28"));
+        data.extend(DISABLE);
+        data.extend(b"M84\n");
+        assert_eq!(decode(&data), "G28\nM84");
+    }
+}
@@ -0,0 +1,425 @@
+use crate::gcode::bgcode::{is_bgcode, read_bgcode};
+use crate::gcode::thumbnail::ThumbnailParser;
+use crate::models::metadata::{GcodeMetadata, Slicer};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+lazy_static! {
+    // ; generated by PrusaSlicer 2.6.0+linux-x64-GTK3 on 2023-06-01 at 10:00:00 UTC
+    static ref RE_PRUSA_FAMILY: Regex =
+        Regex::new(r"generated by (PrusaSlicer|SuperSlicer|OrcaSlicer) (\S+)").unwrap();
+    // ;Generated with Cura_SteamEngine 5.3.0
+    static ref RE_CURA: Regex = Regex::new(r"Generated with Cura_SteamEngine (\S+)").unwrap();
+    // ; G-Code generated by Simplify3D(R) Version 4.1.2
+    static ref RE_SIMPLIFY3D: Regex = Regex::new(r"Simplify3D\(R\) Version (\S+)").unwrap();
+    // ;Sliced by ideaMaker 4.2.1.6
+    static ref RE_IDEAMAKER: Regex = Regex::new(r"Sliced by ideaMaker (\S+)").unwrap();
+
+    // ; model printing time: 1h 2m 3s; total estimated time: 1h 5m 3s
+    static ref RE_ORCA_TIME: Regex = Regex::new(r"total estimated time:\s*(.+)").unwrap();
+    // ; printing object cube.stl id:0 copy 0
+    static ref RE_PRINTING_OBJECT: Regex = Regex::new(r"^printing object (.+)").unwrap();
+
+    // 1d 2h 3m 4s or 1 hours 2 minutes
+    static ref RE_DURATION_PART: Regex =
+        Regex::new(r"(\d+(?:\.\d+)?)\s*(d|h|m|s|days?|hours?|minutes?|seconds?)\b").unwrap();
+    static ref RE_NUMBER: Regex = Regex::new(r"-?\d+(?:\.\d+)?").unwrap();
+
+    // temperatures set by gcode commands, used if the slicer comments contain none
+    static ref RE_NOZZLE_TEMP_CMD: Regex = Regex::new(r"^M10[49]\s.*S(\d+(?:\.\d+)?)").unwrap();
+    static ref RE_BED_TEMP_CMD: Regex = Regex::new(r"^M1[49]0\s.*S(\d+(?:\.\d+)?)").unwrap();
+}
+
+/// Reads slicer metadata from gcode lines
+#[derive(Default)]
+pub struct MetadataParser {
+    metadata: GcodeMetadata,
+    cmd_nozzle_temp: Option<f32>,
+    cmd_bed_temp: Option<f32>,
+    s3d_temp_names: Vec<String>,
+    s3d_temps: Vec<f32>,
+    thumbnails: ThumbnailParser,
+}
+
+impl MetadataParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// parse a single line of a gcode file
+    pub fn parse_line(&mut self, line: &str) {
+        if self.thumbnails.parse_line(line) {
+            return;
+        }
+
+        let line = line.trim();
+        match line.strip_prefix(';') {
+            Some(comment) => self.parse_comment(comment.trim()),
+            None => self.parse_command(line),
+        }
+    }
+
+    /// the collected metadata after all lines are parsed
+    pub fn finish(mut self) -> GcodeMetadata {
+        self.metadata.thumbnails = self
+            .thumbnails
+            .finish()
+            .into_iter()
+            .map(|t| t.info)
+            .collect();
+
+        // Simplify3D lists the temperature names and set points separately
+        for (name, temp) in self.s3d_temp_names.iter().zip(self.s3d_temps.iter()) {
+            if name.to_lowercase().contains("bed") {
+                self.metadata.bed_temp.get_or_insert(*temp);
+            } else {
+                self.metadata.nozzle_temp.get_or_insert(*temp);
+            }
+        }
+
+        if self.metadata.nozzle_temp.is_none() {
+            self.metadata.nozzle_temp = self.cmd_nozzle_temp;
+        }
+        if self.metadata.bed_temp.is_none() {
+            self.metadata.bed_temp = self.cmd_bed_temp;
+        }
+        self.metadata
+    }
+
+    fn parse_command(&mut self, line: &str) {
+        if self.cmd_nozzle_temp.is_none() {
+            self.cmd_nozzle_temp = capture_f32(&RE_NOZZLE_TEMP_CMD, line).filter(|t| *t > 0.0);
+        }
+        if self.cmd_bed_temp.is_none() {
+            self.cmd_bed_temp = capture_f32(&RE_BED_TEMP_CMD, line).filter(|t| *t > 0.0);
+        }
+    }
+
+    fn parse_comment(&mut self, comment: &str) {
+        if self.metadata.slicer.is_none() && self.parse_slicer(comment) {
+            return;
+        }
+
+        if let Some(c) = RE_ORCA_TIME.captures(comment) {
+            self.metadata.estimated_time = parse_duration(&c[1]);
+            return;
+        }
+
+        if let Some(c) = RE_PRINTING_OBJECT.captures(comment) {
+            self.add_object(c[1].trim());
+            return;
+        }
+
+        // PrusaSlicer style "key = value", Cura/ideaMaker style "KEY:value", Simplify3D style "key,value"
+        let (key, value) = if let Some((k, v)) = comment.split_once(" = ") {
+            (k, v)
+        } else if let Some((k, v)) = comment.split_once(':') {
+            (k, v)
+        } else if let Some((k, v)) = comment.split_once(',') {
+            (k, v)
+        } else {
+            return;
+        };
+        self.parse_key_value(key.trim().to_lowercase().as_str(), value.trim());
+    }
+
+    fn parse_slicer(&mut self, comment: &str) -> bool {
+        let (slicer, version) = if let Some(c) = RE_PRUSA_FAMILY.captures(comment) {
+            let slicer = match &c[1] {
+                "SuperSlicer" => Slicer::SuperSlicer,
+                "OrcaSlicer" => Slicer::OrcaSlicer,
+                _ => Slicer::PrusaSlicer,
+            };
+            (slicer, c[2].to_string())
+        } else if let Some(c) = RE_CURA.captures(comment) {
+            (Slicer::Cura, c[1].to_string())
+        } else if let Some(c) = RE_SIMPLIFY3D.captures(comment) {
+            (Slicer::Simplify3D, c[1].to_string())
+        } else if let Some(c) = RE_IDEAMAKER.captures(comment) {
+            (Slicer::IdeaMaker, c[1].to_string())
+        } else {
+            return false;
+        };
+
+        self.metadata.slicer = Some(slicer);
+        self.metadata.slicer_version = Some(version);
+        true
+    }
+
+    fn parse_key_value(&mut self, key: &str, value: &str) {
+        let m = &mut self.metadata;
+        match key {
+            // print time
+            "estimated printing time (normal mode)" | "build time" => {
+                m.estimated_time = parse_duration(value);
+            }
+            "time" | "print time" => {
+                m.estimated_time = first_number(value).map(|t| t as u32);
+            }
+
+            // filament usage
+            "filament used [mm]" => m.filament_length = parse_list(value),
+            "filament used [g]" | "filament weight" => m.filament_weight = parse_list(value),
+            "filament used" => {
+                // Cura reports meters with a unit suffix, ideaMaker mm without
+                m.filament_length = value
+                    .split(',')
+                    .filter_map(|v| {
+                        let v = v.trim();
+                        let mm = first_number(v)?;
+                        Some(if v.ends_with('m') && !v.ends_with("mm") {
+                            mm * 1000.0
+                        } else {
+                            mm
+                        })
+                    })
+                    .collect();
+            }
+            "filament length" => m.filament_length = first_number(value).into_iter().collect(),
+            "plastic weight" => m.filament_weight = first_number(value).into_iter().collect(),
+
+            // layer and nozzle
+            "layer_height" | "layer height" | "layerheight" => {
+                m.layer_height = first_number(value);
+            }
+            "nozzle_diameter" | "extruder_train.0.nozzle.diameter" | "extruderdiameter" => {
+                m.nozzle_diameter = first_number(value);
+            }
+            "max_layer_z" | "maxz" => m.max_layer_z = first_number(value),
+
+            // temperatures
+            "temperature" | "nozzle_temperature" | "extruder_train.0.initial_temperature" => {
+                m.nozzle_temp = first_number(value);
+            }
+            "bed_temperature" | "hot_plate_temp" | "build_plate.initial_temperature" => {
+                m.bed_temp = first_number(value);
+            }
+            "temperaturename" => {
+                self.s3d_temp_names = value.split(',').map(|v| v.trim().to_string()).collect();
+            }
+            "temperaturesetpointtemperatures" => self.s3d_temps = parse_list(value),
+            k if k.contains("temperature") && (k.contains("extruder") || k.contains("nozzle")) => {
+                m.nozzle_temp = m.nozzle_temp.or(first_number(value));
+            }
+            k if k.contains("temperature") && (k.contains("bed") || k.contains("plate")) => {
+                m.bed_temp = m.bed_temp.or(first_number(value));
+            }
+
+            // objects
+            "mesh" if value != "NONMESH" => self.add_object(value),
+            _ => {}
+        }
+    }
+
+    fn add_object(&mut self, name: &str) {
+        if !self.metadata.object_names.iter().any(|n| n == name) {
+            self.metadata.object_names.push(name.to_string());
+        }
+    }
+}
+
+/// read the slicer metadata of the gcode or binary gcode file at the given path
+pub fn analyze_file(path: impl AsRef<Path>) -> anyhow::Result<GcodeMetadata> {
+    if is_bgcode(&path)? {
+        return Ok(read_bgcode(path)?.metadata);
+    }
+    let reader = BufReader::new(File::open(path)?);
+    let mut parser = MetadataParser::new();
+    for line in reader.lines() {
+        parser.parse_line(&line?);
+    }
+    Ok(parser.finish())
+}
+
+/// parse a duration like "1d 2h 3m 4s" or "1 hours 2 minutes" to seconds
+fn parse_duration(value: &str) -> Option<u32> {
+    let mut secs = 0.0;
+    let mut found = false;
+    for c in RE_DURATION_PART.captures_iter(value) {
+        let n: f32 = c[1].parse().unwrap_or(0.0);
+        secs += match &c[2][..1] {
+            "d" => n * 86_400.0,
+            "h" => n * 3_600.0,
+            "m" => n * 60.0,
+            _ => n,
+        };
+        found = true;
+    }
+    found.then_some(secs as u32)
+}
+
+fn first_number(value: &str) -> Option<f32> {
+    RE_NUMBER.find(value).and_then(|m| m.as_str().parse().ok())
+}
+
+fn parse_list(value: &str) -> Vec<f32> {
+    value.split(',').filter_map(first_number).collect()
+}
+
+fn capture_f32(re: &Regex, line: &str) -> Option<f32> {
+    re.captures(line).and_then(|c| c[1].parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(gcode: &str) -> GcodeMetadata {
+        let mut parser = MetadataParser::new();
+        for line in gcode.lines() {
+            parser.parse_line(line);
+        }
+        parser.finish()
+    }
+
+    #[test]
+    fn prusaslicer() {
+        let m = parse(
+            "; generated by PrusaSlicer 2.6.0+linux-x64-GTK3 on 2023-06-01 at 10:00:00 UTC
+M104 S215
+; printing object cube.stl id:0 copy 0
+G1 X10 E1
+; stop printing object cube.stl id:0 copy 0
+; filament used [mm] = 1234.56
+; filament used [g] = 3.70
+; estimated printing time (normal mode) = 1h 2m 3s
+; layer_height = 0.2
+; nozzle_diameter = 0.4
+; temperature = 215
+; bed_temperature = 60
+; max_layer_z = 10.2",
+        );
+        assert_eq!(m.slicer, Some(Slicer::PrusaSlicer));
+        assert_eq!(m.slicer_version.as_deref(), Some("2.6.0+linux-x64-GTK3"));
+        assert_eq!(m.estimated_time, Some(3723));
+        assert_eq!(m.filament_length, vec![1234.56]);
+        assert_eq!(m.filament_weight, vec![3.7]);
+        assert_eq!(m.layer_height, Some(0.2));
+        assert_eq!(m.nozzle_diameter, Some(0.4));
+        assert_eq!(m.nozzle_temp, Some(215.0));
+        assert_eq!(m.bed_temp, Some(60.0));
+        assert_eq!(m.max_layer_z, Some(10.2));
+        assert_eq!(m.object_names, vec!["cube.stl id:0 copy 0"]);
+    }
+
+    #[test]
+    fn superslicer() {
+        let m = parse(
+            "; generated by SuperSlicer 2.5.59 on 2023-06-01 at 10:00:00 UTC
+; filament used [mm] = 100.5, 20.25
+; estimated printing time (normal mode) = 1d 0h 0m 10s
+; first_layer_temperature = 220,230
+; temperature = 210,225
+; first_layer_bed_temperature = 65",
+        );
+        assert_eq!(m.slicer, Some(Slicer::SuperSlicer));
+        assert_eq!(m.estimated_time, Some(86_410));
+        assert_eq!(m.filament_length, vec![100.5, 20.25]);
+        assert_eq!(m.nozzle_temp, Some(210.0));
+        assert_eq!(m.bed_temp, Some(65.0));
+    }
+
+    #[test]
+    fn orcaslicer() {
+        let m = parse(
+            "; generated by OrcaSlicer 1.6.3 on 2023-06-01 at 10:00:00
+; model printing time: 1h 2m 3s; total estimated time: 1h 5m 3s
+; nozzle_temperature = 220
+; hot_plate_temp = 55
+; layer_height = 0.16",
+        );
+        assert_eq!(m.slicer, Some(Slicer::OrcaSlicer));
+        assert_eq!(m.slicer_version.as_deref(), Some("1.6.3"));
+        assert_eq!(m.estimated_time, Some(3903));
+        assert_eq!(m.nozzle_temp, Some(220.0));
+        assert_eq!(m.bed_temp, Some(55.0));
+        assert_eq!(m.layer_height, Some(0.16));
+    }
+
+    #[test]
+    fn cura() {
+        let m = parse(
+            ";FLAVOR:Marlin
+;TIME:6666
+;Filament used: 1.5m
+;Layer height: 0.12
+;MAXZ:5.04
+;Generated with Cura_SteamEngine 5.3.0
+M140 S60
+M104 S200
+;MESH:cube.stl
+G1 X10 E1
+;MESH:NONMESH
+;MESH:cylinder.stl",
+        );
+        assert_eq!(m.slicer, Some(Slicer::Cura));
+        assert_eq!(m.slicer_version.as_deref(), Some("5.3.0"));
+        assert_eq!(m.estimated_time, Some(6666));
+        assert_eq!(m.filament_length, vec![1500.0]);
+        assert_eq!(m.layer_height, Some(0.12));
+        assert_eq!(m.max_layer_z, Some(5.04));
+        // temperatures of the commands without slicer comments
+        assert_eq!(m.nozzle_temp, Some(200.0));
+        assert_eq!(m.bed_temp, Some(60.0));
+        assert_eq!(m.object_names, vec!["cube.stl", "cylinder.stl"]);
+    }
+
+    #[test]
+    fn simplify3d() {
+        let m = parse(
+            "; G-Code generated by Simplify3D(R) Version 4.1.2
+;   layerHeight,0.2
+;   extruderDiameter,0.4
+;   temperatureName,Extruder 1,Heated Bed
+;   temperatureSetpointTemperatures,205,70
+;   Build time: 1 hours 2 minutes
+;   Filament length: 4321.0 mm
+;   Plastic weight: 12.5 g",
+        );
+        assert_eq!(m.slicer, Some(Slicer::Simplify3D));
+        assert_eq!(m.slicer_version.as_deref(), Some("4.1.2"));
+        assert_eq!(m.layer_height, Some(0.2));
+        assert_eq!(m.nozzle_diameter, Some(0.4));
+        assert_eq!(m.nozzle_temp, Some(205.0));
+        assert_eq!(m.bed_temp, Some(70.0));
+        assert_eq!(m.estimated_time, Some(3720));
+        assert_eq!(m.filament_length, vec![4321.0]);
+        assert_eq!(m.filament_weight, vec![12.5]);
+    }
+
+    #[test]
+    fn ideamaker() {
+        let m = parse(
+            ";Sliced by ideaMaker 4.2.1.6
+;Print Time: 1234
+;Filament used: 2345.6
+;Filament Weight: 7.1
+;Layer Height: 0.25
+;Extruder 1 Temperature: 210
+;Bed Temperature: 50",
+        );
+        assert_eq!(m.slicer, Some(Slicer::IdeaMaker));
+        assert_eq!(m.slicer_version.as_deref(), Some("4.2.1.6"));
+        assert_eq!(m.estimated_time, Some(1234));
+        assert_eq!(m.filament_length, vec![2345.6]);
+        assert_eq!(m.filament_weight, vec![7.1]);
+        assert_eq!(m.layer_height, Some(0.25));
+        assert_eq!(m.nozzle_temp, Some(210.0));
+        assert_eq!(m.bed_temp, Some(50.0));
+    }
+
+    #[test]
+    fn unparsable_temperatures_are_left_empty() {
+        let m = parse(
+            ";Extruder 1 Temperature: unknown
+;Bed Temperature: -
+M104 S215
+M140 S65",
+        );
+        assert_eq!(m.nozzle_temp, Some(215.0));
+        assert_eq!(m.bed_temp, Some(65.0));
+    }
+}
@@ -0,0 +1,8 @@
+pub mod bgcode;
+pub mod estimator;
+pub(crate) mod layers;
+pub mod meatpack;
+pub mod metadata;
+pub(crate) mod objects;
+pub mod thumbnail;
+pub mod validation;
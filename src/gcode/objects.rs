@@ -0,0 +1,171 @@
+use crate::models::job::{BoundingBox, PrintObject};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    // M486 S0, M486 S-1 (firmware object labels)
+    static ref RE_M486_START: Regex = Regex::new(r"^M486\s.*S(-?\d+)").unwrap();
+    // M486 Acube.stl id:0 copy 0
+    static ref RE_M486_NAME: Regex = Regex::new(r"^M486\s+A\s*(.+)").unwrap();
+    // ; printing object cube.stl id:0 copy 0 / ; stop printing object cube.stl id:0 copy 0 (PrusaSlicer)
+    static ref RE_PRINTING_OBJECT: Regex = Regex::new(r"^;\s*(stop )?printing object (.+)").unwrap();
+    // ;MESH:cube.stl, ;MESH:NONMESH (Cura)
+    static ref RE_MESH: Regex = Regex::new(r"^;MESH:(.+)").unwrap();
+    // EXCLUDE_OBJECT_DEFINE NAME=cube ... / EXCLUDE_OBJECT_START NAME=cube / EXCLUDE_OBJECT_END (Klipper)
+    static ref RE_EXCLUDE_OBJECT: Regex =
+        Regex::new(r"^EXCLUDE_OBJECT_(DEFINE|START|END)(?:\s.*NAME=(\S+))?").unwrap();
+    static ref RE_XY_MOVE: Regex = Regex::new(r"^G[0-3]\s").unwrap();
+    static ref RE_PARAM: Regex = Regex::new(r"([XYE])(-?[\d.]+)").unwrap();
+    static ref RE_EXTRUSION_MODE: Regex = Regex::new(r"^(G9[01]|M8[23])\b").unwrap();
+    static ref RE_E_RESET: Regex = Regex::new(r"^G92\s.*E").unwrap();
+}
+
+/// Finds the labeled objects of a gcode file and the object every line belongs to
+#[derive(Default)]
+pub(crate) struct ObjectDetector {
+    objects: Vec<PrintObject>,
+    current: Option<u32>,
+    /// the file labels objects with M486 which the firmware can cancel itself
+    firmware_labels: bool,
+    x: f32,
+    y: f32,
+    e: f32,
+    relative_e: bool,
+}
+
+impl ObjectDetector {
+    pub(crate) fn parse_line(&mut self, line: &str) {
+        let line = line.trim();
+
+        if let Some(c) = RE_M486_START.captures(line) {
+            self.firmware_labels = true;
+            self.current = c[1].parse::<u32>().ok();
+            if let Some(id) = self.current {
+                self.object_with_id(id);
+            }
+        } else if let Some(c) = RE_M486_NAME.captures(line) {
+            if let Some(id) = self.current {
+                self.object_with_id(id).name = c[1].trim().trim_matches('"').to_string();
+            }
+        } else if let Some(c) = RE_PRINTING_OBJECT.captures(line) {
+            self.current = match c.get(1) {
+                Some(_) => None,
+                None => Some(self.object_with_name(c[2].trim())),
+            };
+        } else if let Some(c) = RE_MESH.captures(line) {
+            self.current = match c[1].trim() {
+                "NONMESH" => None,
+                name => Some(self.object_with_name(name)),
+            };
+        } else if let Some(c) = RE_EXCLUDE_OBJECT.captures(line) {
+            let id = c.get(2).map(|n| self.object_with_name(n.as_str()));
+            match &c[1] {
+                "START" => self.current = id,
+                "END" => self.current = None,
+                _ => {}
+            }
+        } else if !line.starts_with(';') {
+            self.parse_move(line);
+        }
+    }
+
+    /// the object the last parsed line belongs to
+    pub(crate) fn current(&self) -> Option<u32> {
+        self.current
+    }
+
+    /// the found objects and if the file labels them for the firmware
+    pub(crate) fn finish(self) -> (Vec<PrintObject>, bool) {
+        (self.objects, self.firmware_labels)
+    }
+
+    fn parse_move(&mut self, line: &str) {
+        if let Some(c) = RE_EXTRUSION_MODE.captures(line) {
+            self.relative_e = matches!(&c[1], "G91" | "M83");
+        }
+        if RE_E_RESET.is_match(line) {
+            self.e = 0.0;
+        }
+        if !RE_XY_MOVE.is_match(line) {
+            return;
+        }
+
+        let (start_x, start_y) = (self.x, self.y);
+        let mut extruding = false;
+        for c in RE_PARAM.captures_iter(line) {
+            let v: f32 = c[2].parse().unwrap_or(0.0);
+            match &c[1] {
+                "X" => self.x = v,
+                "Y" => self.y = v,
+                _ => {
+                    extruding = if self.relative_e { v > 0.0 } else { v > self.e };
+                    if !self.relative_e {
+                        self.e = v;
+                    }
+                }
+            }
+        }
+
+        // the bounding box contains the extruded moves of the object
+        if let (true, Some(id)) = (extruding, self.current) {
+            let (x, y) = (self.x, self.y);
+            let object = self.object_with_id(id);
+            let b = object.bounds.unwrap_or(BoundingBox {
+                min_x: x,
+                min_y: y,
+                max_x: x,
+                max_y: y,
+            });
+            object.bounds = Some(BoundingBox {
+                min_x: b.min_x.min(x).min(start_x),
+                min_y: b.min_y.min(y).min(start_y),
+                max_x: b.max_x.max(x).max(start_x),
+                max_y: b.max_y.max(y).max(start_y),
+            });
+        }
+    }
+
+    fn object_with_id(&mut self, id: u32) -> &mut PrintObject {
+        let pos = match self.objects.iter().position(|o| o.id == id) {
+            Some(pos) => pos,
+            None => {
+                self.objects.push(PrintObject {
+                    id,
+                    name: format!("Object {}", id),
+                    bounds: None,
+                    cancelled: false,
+                });
+                self.objects.len() - 1
+            }
+        };
+        &mut self.objects[pos]
+    }
+
+    fn object_with_name(&mut self, name: &str) -> u32 {
+        if let Some(o) = self.objects.iter().find(|o| o.name == name) {
+            return o.id;
+        }
+        // ids of M486 labels may have gaps, a new id must not collide with them
+        let id = self.objects.iter().map(|o| o.id + 1).max().unwrap_or(0);
+        self.object_with_id(id).name = name.to_string();
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_objects_get_unused_ids() {
+        let mut detector = ObjectDetector::default();
+        for line in ["M486 S1", "M486 Acube", ";MESH:cylinder", ";MESH:cube"] {
+            detector.parse_line(line);
+        }
+        assert_eq!(detector.current(), Some(1));
+
+        let (objects, _) = detector.finish();
+        let ids: Vec<(u32, &str)> = objects.iter().map(|o| (o.id, o.name.as_str())).collect();
+        assert_eq!(ids, vec![(1, "cube"), (2, "cylinder")]);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::gcode::bgcode::{is_bgcode, read_bgcode};
+use crate::models::metadata::{Thumbnail, ThumbnailFormat, ThumbnailInfo};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+lazy_static! {
+    // ; thumbnail begin 300x300 12345 or ; thumbnail_QOI begin 16x16 1234
+    static ref RE_THUMBNAIL_BEGIN: Regex =
+        Regex::new(r"^;\s*thumbnail(?:_(\w+))?\s+begin\s+(\d+)x(\d+)").unwrap();
+    static ref RE_THUMBNAIL_END: Regex = Regex::new(r"^;\s*thumbnail(?:_\w+)?\s+end").unwrap();
+}
+
+/// Collects the thumbnails embedded between `; thumbnail begin` and `; thumbnail end` comments
+#[derive(Default)]
+pub struct ThumbnailParser {
+    keep_data: bool,
+    current: Option<Thumbnail>,
+    thumbnails: Vec<Thumbnail>,
+}
+
+impl ThumbnailParser {
+    /// create a parser, without `keep_data` only the size and format of the thumbnails is collected
+    pub fn new(keep_data: bool) -> Self {
+        ThumbnailParser {
+            keep_data,
+            ..Default::default()
+        }
+    }
+
+    /// parse a single line of a gcode file, returns true if the line is part of a thumbnail
+    pub fn parse_line(&mut self, line: &str) -> bool {
+        let line = line.trim();
+
+        if let Some(mut t) = self.current.take() {
+            if RE_THUMBNAIL_END.is_match(line) {
+                self.thumbnails.push(t);
+            } else {
+                if self.keep_data {
+                    t.data.push_str(line.trim_start_matches(';').trim());
+                }
+                self.current = Some(t);
+            }
+            return true;
+        }
+
+        match RE_THUMBNAIL_BEGIN.captures(line) {
+            None => false,
+            Some(c) => {
+                let format = match c.get(1).map(|m| m.as_str().to_uppercase()).as_deref() {
+                    Some("QOI") => ThumbnailFormat::Qoi,
+                    Some("JPG") => ThumbnailFormat::Jpg,
+                    _ => ThumbnailFormat::Png,
+                };
+                self.current = Some(Thumbnail {
+                    info: ThumbnailInfo {
+                        width: c[2].parse().unwrap_or(0),
+                        height: c[3].parse().unwrap_or(0),
+                        format,
+                    },
+                    data: String::new(),
+                });
+                true
+            }
+        }
+    }
+
+    /// the collected thumbnails after all lines are parsed
+    pub fn finish(self) -> Vec<Thumbnail> {
+        self.thumbnails
+    }
+}
+
+/// read all thumbnails embedded in the gcode or binary gcode file at the given path
+pub fn extract_thumbnails(path: impl AsRef<Path>) -> anyhow::Result<Vec<Thumbnail>> {
+    if is_bgcode(&path)? {
+        return Ok(read_bgcode(path)?.thumbnails);
+    }
+    let reader = BufReader::new(File::open(path)?);
+    let mut parser = ThumbnailParser::new(true);
+    for line in reader.lines() {
+        parser.parse_line(&line?);
+    }
+    Ok(parser.finish())
+}
@@ -0,0 +1,342 @@
+use crate::gcode::bgcode::open_gcode;
+use crate::models::policy::CommandPolicy;
+use crate::models::profile::PrinterProfile;
+use crate::models::validation::{IssueSeverity, ValidationIssue, ValidationReport};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::Path;
+
+lazy_static! {
+    static ref RE_MOVE: Regex = Regex::new(r"^G[0-3]\b").unwrap();
+    static ref RE_AXIS: Regex = Regex::new(r"([XYZ])\s*(-?\d*\.?\d+)").unwrap();
+    static ref RE_POSITIONING: Regex = Regex::new(r"^G9([01])\b").unwrap();
+    static ref RE_SET_POSITION: Regex = Regex::new(r"^G92\b").unwrap();
+    // G28, G28 X Y
+    static ref RE_HOME: Regex = Regex::new(r"^G28\b(.*)").unwrap();
+    // M104 S210 / M104S210 / M109 R200 / M140 S60 / M190 S60
+    static ref RE_SET_TEMP: Regex = Regex::new(r"^M(104|109|140|190)(?:[^\d.].*)?[SR](\d*\.?\d+)").unwrap();
+    // T1
+    static ref RE_TOOL: Regex = Regex::new(r"^T(\d+)\b").unwrap();
+    static ref RE_CMD: Regex = Regex::new(r"^([GM]\d+)\b").unwrap();
+}
+
+const AXES: [&str; 3] = ["X", "Y", "Z"];
+
+/// commands which need a capability of the firmware, reported as Cap:<NAME>:<0|1> by M115
+const REQUIRED_CAPABILITIES: [(&str, &str); 6] = [
+    ("G2", "ARCS"),
+    ("G3", "ARCS"),
+    ("G29", "AUTOLEVEL"),
+    ("M141", "CHAMBER_TEMPERATURE"),
+    ("M191", "CHAMBER_TEMPERATURE"),
+    ("M290", "BABYSTEPPING"),
+];
+
+/// Checks gcode lines against the build volume, heater limits, extruder count
+/// and the capabilities of the firmware
+pub struct GcodeValidator {
+    profile: PrinterProfile,
+    capabilities: HashMap<String, bool>,
+    policy: CommandPolicy,
+    /// position per axis, unknown until the first absolute move after homing
+    pos: [Option<f32>; 3],
+    relative_moves: bool,
+    homed: bool,
+    line: u32,
+    /// issues with a key to count lines with the same issue
+    issues: Vec<(String, ValidationIssue)>,
+}
+
+impl GcodeValidator {
+    pub fn new(profile: PrinterProfile, capabilities: HashMap<String, bool>) -> Self {
+        GcodeValidator {
+            profile,
+            capabilities,
+            policy: CommandPolicy::default(),
+            pos: [None; 3],
+            relative_moves: false,
+            homed: false,
+            line: 0,
+            issues: vec![],
+        }
+    }
+
+    /// also check the lines against the command policy
+    pub fn with_policy(mut self, policy: CommandPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// check the next line of the file
+    pub fn add_line(&mut self, line: &str) {
+        self.line += 1;
+        let line = line.split(';').next().unwrap_or("").trim();
+        if line.is_empty() {
+            return;
+        }
+
+        if let Some(reason) = self.policy.violation(line) {
+            self.add_issue(format!("policy {}", reason), IssueSeverity::Denied, reason);
+        }
+
+        if let Some(c) = RE_POSITIONING.captures(line) {
+            self.relative_moves = &c[1] == "1";
+        } else if let Some(c) = RE_HOME.captures(line) {
+            self.homed = true;
+            // the position after homing depends on the endstops
+            let args = c[1].to_uppercase();
+            let all = !AXES.iter().any(|a| args.contains(a));
+            for (i, axis) in AXES.iter().enumerate() {
+                if all || args.contains(axis) {
+                    self.pos[i] = None;
+                }
+            }
+        } else if RE_SET_POSITION.is_match(line) {
+            for c in RE_AXIS.captures_iter(line) {
+                self.pos[axis_index(&c[1])] = c[2].parse().ok();
+            }
+        } else if RE_MOVE.is_match(line) {
+            self.check_move(line);
+        } else if let Some(c) = RE_SET_TEMP.captures(line) {
+            self.check_temp(&c[1], c[2].parse().unwrap_or(0.0));
+        } else if let Some(c) = RE_TOOL.captures(line) {
+            let tool: u8 = c[1].parse().unwrap_or(u8::MAX);
+            if let Some(extruders) = self.profile.extruders.filter(|e| tool >= *e) {
+                let msg = format!(
+                    "tool T{} is used but the printer has {} extruder(s)",
+                    tool, extruders
+                );
+                self.add_issue(format!("tool {}", tool), IssueSeverity::Error, msg);
+            }
+        }
+
+        if let Some(c) = RE_CMD.captures(line) {
+            let cmd = &c[1];
+            for (_, cap) in REQUIRED_CAPABILITIES.iter().filter(|(r, _)| *r == cmd) {
+                if self.capabilities.get(*cap) == Some(&false) {
+                    let msg = format!("{} needs {} which the firmware does not support", cmd, cap);
+                    self.add_issue(format!("cap {}", cmd), IssueSeverity::Error, msg);
+                }
+            }
+        }
+    }
+
+    pub fn finish(self) -> ValidationReport {
+        ValidationReport {
+            issues: self.issues.into_iter().map(|(_, i)| i).collect(),
+        }
+    }
+
+    fn check_move(&mut self, line: &str) {
+        if !self.homed && RE_AXIS.is_match(line) {
+            let msg = "moves are sent before the printer is homed (G28)".to_string();
+            self.add_issue("not homed".to_string(), IssueSeverity::Warning, msg);
+        }
+
+        for c in RE_AXIS.captures_iter(line) {
+            let i = axis_index(&c[1]);
+            let Ok(v) = c[2].parse::<f32>() else {
+                continue;
+            };
+
+            let target = match (self.relative_moves, self.pos[i]) {
+                (false, _) => Some(v),
+                (true, Some(p)) => Some(p + v),
+                (true, None) => None,
+            };
+            self.pos[i] = target;
+
+            let (Some(target), Some(volume)) = (target, self.profile.build_volume) else {
+                continue;
+            };
+            let (min, max) = (volume.min[i], volume.max[i]);
+            if target < min || target > max {
+                let msg = format!(
+                    "{} moves to {} outside of the build volume {}..{}",
+                    AXES[i], target, min, max
+                );
+                self.add_issue(format!("volume {}", AXES[i]), IssueSeverity::Error, msg);
+            }
+        }
+    }
+
+    fn check_temp(&mut self, cmd: &str, temp: f32) {
+        let (heater, max) = match cmd {
+            "104" | "109" => ("extruder", self.profile.heaters.max_extruder_temp),
+            _ => ("bed", self.profile.heaters.max_bed_temp),
+        };
+        if let Some(max) = max.filter(|m| temp > *m) {
+            let msg = format!(
+                "{} temperature {}°C is above the limit of {}°C",
+                heater, temp, max
+            );
+            self.add_issue(format!("temp {}", heater), IssueSeverity::Error, msg);
+        }
+    }
+
+    /// add an issue or count the line if the issue was found before
+    fn add_issue(&mut self, key: String, severity: IssueSeverity, message: String) {
+        match self.issues.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, issue)) => issue.count += 1,
+            None => self.issues.push((
+                key,
+                ValidationIssue {
+                    severity,
+                    line: self.line,
+                    count: 1,
+                    message,
+                },
+            )),
+        }
+    }
+}
+
+fn axis_index(axis: &str) -> usize {
+    AXES.iter().position(|a| *a == axis).unwrap_or(0)
+}
+
+/// check the gcode file at the given path against the printer profile and the capabilities of the firmware
+pub fn validate_file(
+    path: impl AsRef<Path>,
+    profile: PrinterProfile,
+    capabilities: HashMap<String, bool>,
+) -> anyhow::Result<ValidationReport> {
+    let mut validator = GcodeValidator::new(profile, capabilities);
+    for line in open_gcode(path)?.lines() {
+        validator.add_line(&line?);
+    }
+    Ok(validator.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::profile::{BuildVolume, HeaterLimits};
+
+    fn profile() -> PrinterProfile {
+        PrinterProfile {
+            heaters: HeaterLimits {
+                max_extruder_temp: Some(260.0),
+                max_bed_temp: Some(110.0),
+            },
+            build_volume: Some(BuildVolume {
+                min: [0.0, 0.0, 0.0],
+                max: [200.0, 200.0, 180.0],
+            }),
+            extruders: Some(1),
+            ..Default::default()
+        }
+    }
+
+    fn validate(validator: GcodeValidator, lines: &[&str]) -> Vec<(IssueSeverity, u32, u32)> {
+        let mut validator = validator;
+        for line in lines {
+            validator.add_line(line);
+        }
+        validator
+            .finish()
+            .issues
+            .iter()
+            .map(|i| (i.severity, i.line, i.count))
+            .collect()
+    }
+
+    fn validate_profile(profile: PrinterProfile, lines: &[&str]) -> Vec<(IssueSeverity, u32, u32)> {
+        validate(GcodeValidator::new(profile, HashMap::new()), lines)
+    }
+
+    #[test]
+    fn valid_file() {
+        let lines = [
+            "G28 ; home",
+            "M104 S210",
+            "M140S60",
+            "G1 X10 Y10 Z0.2 F3000",
+            "G1 X190 Y190 E5",
+            "T0",
+        ];
+        assert!(validate_profile(profile(), &lines).is_empty());
+    }
+
+    #[test]
+    fn moves_outside_of_the_build_volume() {
+        let lines = ["G28", "G1 X10 Y10", "G1 X210 Y-5", "G1 X220"];
+        assert_eq!(
+            validate_profile(profile(), &lines),
+            vec![(IssueSeverity::Error, 3, 2), (IssueSeverity::Error, 3, 1)]
+        );
+    }
+
+    #[test]
+    fn relative_moves_add_up() {
+        let lines = [
+            "G28", "G1 X150", "G91", "G1 X40", "G1 X40", "G90", "G92 X0", "G1 X150",
+        ];
+        assert_eq!(
+            validate_profile(profile(), &lines),
+            vec![(IssueSeverity::Error, 5, 1)]
+        );
+    }
+
+    #[test]
+    fn moves_before_homing() {
+        let lines = ["G1 X10", "G28", "G1 X20"];
+        assert_eq!(
+            validate_profile(profile(), &lines),
+            vec![(IssueSeverity::Warning, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn temperatures_and_tools() {
+        let lines = ["M104 S280", "M109 R270", "M190 S120", "T1"];
+        assert_eq!(
+            validate_profile(profile(), &lines),
+            vec![
+                (IssueSeverity::Error, 1, 2),
+                (IssueSeverity::Error, 3, 1),
+                (IssueSeverity::Error, 4, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn limits_are_only_checked_if_set() {
+        let lines = ["G28", "G1 X500", "M104 S300", "M140 S130", "T3"];
+        assert!(validate_profile(PrinterProfile::default(), &lines).is_empty());
+    }
+
+    #[test]
+    fn missing_capabilities() {
+        let capabilities = HashMap::from([("ARCS".to_string(), false)]);
+        let validator = GcodeValidator::new(PrinterProfile::default(), capabilities);
+        let lines = ["G28", "G2 X10 Y10 I5", "G3 X0 Y0 I-5", "G29"];
+        // G29 is not checked as the firmware did not report AUTOLEVEL
+        assert_eq!(
+            validate(validator, &lines),
+            vec![(IssueSeverity::Error, 2, 1), (IssueSeverity::Error, 3, 1)]
+        );
+    }
+
+    #[test]
+    fn denied_lines() {
+        let policy = CommandPolicy {
+            deny: vec!["M500".to_string()],
+            ..Default::default()
+        };
+        let validator =
+            GcodeValidator::new(PrinterProfile::default(), HashMap::new()).with_policy(policy);
+        let report = {
+            let mut validator = validator;
+            for line in ["G28", "M500 ; save", "M500"] {
+                validator.add_line(line);
+            }
+            validator.finish()
+        };
+        assert!(report.is_denied());
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].count, 2);
+    }
+}
@@ -1,27 +1,62 @@
+use crate::gcode::bgcode::{is_bgcode, open_gcode, read_bgcode};
+use crate::gcode::estimator::TimeEstimator;
+use crate::gcode::layers::LayerDetector;
+use crate::gcode::metadata::MetadataParser;
+use crate::gcode::objects::ObjectDetector;
+use crate::gcode::thumbnail::extract_thumbnails;
+use crate::gcode::validation::GcodeValidator;
+use crate::history::PrintHistory;
 use crate::models::action::{Action, Command, PrinterAction, PrinterStatus, TelemetryData};
-use crate::models::file::{FinishedPrint, GcodeFile};
+use crate::models::file::{FinishedPrint, GcodeFile, JobStats, PrintOutcome};
+use crate::models::job::{AutoStart, DisplayUpdates, HookTrigger, JobState, LayerHook, QueuedJob};
+use crate::models::metadata::GcodeMetadata;
+use crate::models::motion::{Axis, Coordinates, MotionModes};
+use crate::models::policy::CommandPolicy;
+use crate::models::profile::PrinterProfile;
 use crate::models::serial_connector::SerialConnector;
-use crate::serial::event_loop::Serial;
+use crate::models::temperature::{IdleTimeout, ThermalWatchdog};
+use crate::models::validation::ValidationReport;
+use crate::serial::boot::HANDSHAKE;
+use crate::serial::display::display_message_cmd;
+use crate::serial::event_loop::{Serial, SharedState};
+use crate::serial::host_command::{HOST_CMD_IDLE, HOST_CMD_LAYER, HOST_CMD_MEATPACK};
+use crate::serial::job::{Job, LinePosition};
+use crate::serial::sd_card::SdUpload;
+use anyhow::{anyhow, bail};
 use event_listener::Event;
-use lazy_static::lazy_static;
 use log::{debug, warn};
-use regex::Regex;
 use std::collections::VecDeque;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::broadcast::{Receiver, Sender};
 
-lazy_static! {
-    static ref RE_MAX_Z_POS: Regex = Regex::new(r";\s*max_layer_z\s*=\s*([\d.]+)").unwrap();
-}
+mod job_queue;
+mod policy;
+mod sd_card;
 
 pub struct GcodeSerial {
     tx: Sender<Action>,
     que: Arc<Mutex<VecDeque<String>>>,
     event: Arc<Mutex<Event>>,
     active_file: Option<GcodeFile>,
+    motion_modes: Arc<Mutex<MotionModes>>,
+    printer_status: Arc<Mutex<PrinterStatus>>,
+    job_queue: VecDeque<QueuedJob>,
+    next_job_id: u32,
+    auto_start: AutoStart,
+    awaiting_bed_clear: bool,
+    job: Job,
+    history: Option<PrintHistory>,
+    profile: PrinterProfile,
+    sd_upload: Arc<Mutex<Option<SdUpload>>>,
+    /// the active print labels its objects with M486
+    firmware_labels: bool,
+    policy: CommandPolicy,
 }
 
 impl GcodeSerial {
@@ -33,18 +68,40 @@ impl GcodeSerial {
             que: q,
             event,
             active_file: None,
+            motion_modes: Arc::new(Mutex::new(MotionModes::default())),
+            printer_status: Arc::new(Mutex::new(PrinterStatus::Disconnected)),
+            job_queue: VecDeque::new(),
+            next_job_id: 0,
+            auto_start: AutoStart::Off,
+            awaiting_bed_clear: false,
+            job: Job::new(tx.clone()),
+            history: None,
+            profile: PrinterProfile::default(),
+            sd_upload: Arc::new(Mutex::new(None)),
+            firmware_labels: false,
+            policy: CommandPolicy::default(),
         }
     }
 
     /// connect to printer and initialize lib
     pub async fn start(&mut self, serial_connector: SerialConnector) {
         let rx = self.tx.subscribe();
+        self.que
+            .lock()
+            .unwrap()
+            .extend(HANDSHAKE.iter().map(|c| c.to_string()));
 
-        let que = self.que.clone();
-        let event = self.event.clone();
         let tx = self.tx.clone();
+        let shared = SharedState {
+            que: self.que.clone(),
+            event: self.event.clone(),
+            motion_modes: self.motion_modes.clone(),
+            printer_status: self.printer_status.clone(),
+            job: self.job.clone(),
+            sd_upload: self.sd_upload.clone(),
+        };
         tokio::spawn(async move {
-            let mut serial = Serial::new(tx, serial_connector, que, event).await;
+            let mut serial = Serial::new(tx, serial_connector, shared).await;
             serial.start_temp_interval();
             serial.start_event_loop().await;
         });
@@ -54,42 +111,166 @@ impl GcodeSerial {
 
     /// set target temperatures of bed and extruder
     pub fn set_temps(&mut self, bed_temp: u16, extruder_temp: u16) {
-        self.que
-            .lock()
-            .unwrap()
-            .push_back(format!("M140 S{}", bed_temp));
-        self.que
-            .lock()
-            .unwrap()
-            .push_back(format!("M104 S{}", extruder_temp));
+        self.push_manual(vec![
+            format!("M140 S{}", bed_temp),
+            format!("M104 S{}", extruder_temp),
+        ]);
+    }
+
+    /// set feedrate override in percent
+    pub fn set_speed_factor(&self, percent: u32) {
+        self.push_priority(format!("M220 S{}", percent));
+    }
+
+    /// set flow override in percent of the given extruder
+    pub fn set_flow_factor(&self, extruder: u8, percent: u32) {
+        self.push_priority(format!("M221 S{} T{}", percent, extruder));
+    }
+
+    /// set speed of the given fan (0.0-1.0)
+    pub fn set_fan_speed(&self, fan: u8, speed: f32) {
+        let pwm = (speed.clamp(0.0, 1.0) * 255.0).round() as u32;
+        self.push_priority(format!("M106 P{} S{}", fan, pwm));
+    }
+
+    /// babystep the z axis by the given distance in mm
+    pub fn babystep(&self, z: f32) {
+        self.push_priority(format!("M290 Z{:.3}", z));
+    }
+
+    /// home the given axes, all axes if empty
+    pub fn home(&self, axes: &[Axis]) -> anyhow::Result<()> {
+        self.ensure_no_active_job()?;
+        let params = axes
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<String>>()
+            .join(" ");
+        self.push_manual(vec![format!("G28 {}", params).trim().to_string()]);
+        Ok(())
+    }
+
+    /// move relative to the current position with feedrate in mm/min
+    pub fn jog(&self, distance: &Coordinates, feedrate: u32) -> anyhow::Result<()> {
+        self.ensure_no_active_job()?;
+        let mut cmds = vec![
+            "G91".to_string(),
+            format!("G1 {} F{}", distance.to_params()?, feedrate),
+        ];
+        cmds.extend(self.motion_modes.lock().unwrap().restore_cmds());
+        self.push_manual(cmds);
+        Ok(())
+    }
+
+    /// move to absolute coordinates with feedrate in mm/min
+    pub fn move_to(&self, position: &Coordinates, feedrate: u32) -> anyhow::Result<()> {
+        self.ensure_no_active_job()?;
+        let mut cmds = vec![
+            "G90".to_string(),
+            format!("G1 {} F{}", position.to_params()?, feedrate),
+        ];
+        cmds.extend(self.motion_modes.lock().unwrap().restore_cmds());
+        self.push_manual(cmds);
+        Ok(())
+    }
+
+    /// extrude given length in mm, negative length retracts
+    pub fn extrude(&self, length: f32, feedrate: u32) -> anyhow::Result<()> {
+        self.ensure_no_active_job()?;
+        let mut cmds = vec![
+            "M83".to_string(),
+            format!("G1 E{:.3} F{}", length, feedrate),
+        ];
+        cmds.extend(self.motion_modes.lock().unwrap().restore_cmds());
+        self.push_manual(cmds);
+        Ok(())
+    }
+
+    /// disable all stepper motors
+    pub fn disable_motors(&self) -> anyhow::Result<()> {
+        self.ensure_no_active_job()?;
+        self.push_manual(vec!["M84".to_string()]);
+        Ok(())
     }
 
     /// start a new print of given gcode file path
-    /// won't start file if event que size > 10
-    pub fn start_print(&mut self, file_path: String) {
-        // if we have a large que we don't do anything
-        let que_len = self.que.lock().unwrap().len();
-        if que_len > 10 {
-            warn!(
-                "Failed to start new print. Que has still {} elements",
-                que_len
-            );
-            return;
+    /// won't start file if another print is active or the file fails the validation against the printer profile
+    pub fn start_print(&mut self, file_path: String) -> anyhow::Result<()> {
+        self.start_validated_print(file_path, false)
+    }
+
+    /// start a new print of given gcode file path even if the file fails the validation
+    pub fn start_print_forced(&mut self, file_path: String) -> anyhow::Result<()> {
+        self.start_validated_print(file_path, true)
+    }
+
+    /// check the gcode file at the given path against the printer profile and the capabilities of the firmware
+    pub fn validate_file(&self, file_path: &str) -> anyhow::Result<ValidationReport> {
+        let mut validator = self.validator();
+        for line in open_gcode(file_path)?.lines() {
+            validator.add_line(&line?);
         }
+        Ok(validator.finish())
+    }
+
+    /// checks files against the profile, the capabilities of the firmware and the command policy
+    fn validator(&self) -> GcodeValidator {
+        let capabilities = self.job.capabilities.lock().unwrap().clone();
+        GcodeValidator::new(self.profile.clone(), capabilities).with_policy(self.policy.clone())
+    }
+
+    fn start_validated_print(&mut self, file_path: String, force: bool) -> anyhow::Result<()> {
+        self.ensure_no_active_job()?;
+        self.ensure_no_upload()?;
 
-        let file = File::open(&file_path).unwrap();
+        let file = File::open(&file_path)?;
+        let display_name = Path::new(&file_path)
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
 
         let unix_timestamp = file
-            .metadata()
-            .unwrap()
-            .modified()
-            .unwrap()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
+            .metadata()?
+            .modified()?
+            .duration_since(UNIX_EPOCH)?
             .as_millis();
-        let size = file.metadata().unwrap().len();
+        let size = file.metadata()?.len();
+
+        let mut validator = self.validator();
 
-        let reader = BufReader::new(file);
+        // binary gcode keeps its metadata in separate blocks instead of comments
+        let metadata = if is_bgcode(&file_path)? {
+            let bgcode = read_bgcode(&file_path)?;
+            self.load_file_to_que(Cursor::new(bgcode.gcode), &mut validator)?;
+            bgcode.metadata
+        } else {
+            self.load_file_to_que(BufReader::new(file), &mut validator)?
+        };
+
+        let report = validator.finish();
+        if !report.issues.is_empty() {
+            let _ = self
+                .tx
+                .send(Action::Telemetry(TelemetryData::ValidationReport(
+                    file_path.clone(),
+                    report.clone(),
+                )));
+        }
+        // the command policy can't be overridden by forcing the start
+        if report.is_denied() || (report.has_errors() && !force) {
+            self.job.lines.lock().unwrap().clear();
+            self.job.positions.lock().unwrap().clear();
+            self.job.objects.lock().unwrap().clear();
+            if report.is_denied() {
+                bail!("File {} is denied by the command policy", file_path);
+            }
+            bail!(
+                "File {} failed the validation against the printer profile",
+                file_path
+            );
+        }
+        let max_layer_z = metadata.max_layer_z;
 
         let active_file = GcodeFile {
             name: file_path,
@@ -99,66 +280,291 @@ impl GcodeSerial {
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
                 .as_millis(),
+            metadata,
         };
         self.active_file = Some(active_file.clone());
+        *self.job.stats.lock().unwrap() = JobStats::default();
+        if let Some(history) = self.history.as_mut() {
+            if let Err(e) = history.set_active(Some(active_file.clone())) {
+                warn!("Failed to write print history: {}", e);
+            }
+        }
 
         let _ = self
             .tx
             .send(Action::Telemetry(TelemetryData::ActiveFileChange(Some(
-                active_file,
+                Box::new(active_file),
             ))));
+        if let Some(z) = max_layer_z {
+            let _ = self
+                .tx
+                .send(Action::Telemetry(TelemetryData::MaxZHeight(z)));
+        }
+
+        let objects = self.job.objects.lock().unwrap().clone();
+        if !objects.is_empty() {
+            let _ = self
+                .tx
+                .send(Action::Telemetry(TelemetryData::Objects(objects)));
+        }
 
-        self.load_file_to_que(reader);
+        self.job.transition(JobState::Queued);
+        self.show_message(&display_name);
 
         let _ = self
             .tx
             .send(Action::Telemetry(TelemetryData::TotalCommandCount(
-                self.que.lock().unwrap().len() as u32,
+                self.job.lines.lock().unwrap().len() as u32,
             )));
-        let _ = self.tx.send(Action::StateChange(PrinterStatus::Active));
+        self.update_status(PrinterStatus::Active);
         self.event.lock().unwrap().notify(42);
+        Ok(())
     }
 
     /// stop the active print and add the stop gcode to que
-    pub fn stop_print(&self) {
+    pub fn stop_print(&mut self) {
+        if self.job.is_active() {
+            self.job.lines.lock().unwrap().clear();
+            self.job.transition(JobState::Cancelling);
+        }
         self.clear_que_add_ending_code();
 
         let _ = self
             .tx
             .send(Action::Telemetry(TelemetryData::TotalCommandCount(0)));
-        let _ = self.tx.send(Action::StateChange(PrinterStatus::Idle));
         self.event.lock().unwrap().notify(42);
     }
 
+    /// pause the active print after the running moves finished
+    pub fn pause_print(&mut self) -> anyhow::Result<()> {
+        match self.job.state() {
+            Some(JobState::Heating | JobState::Printing) => {
+                self.job.transition(JobState::Pausing);
+                // wait for the planned moves to finish before the print counts as paused
+                self.push_job_control("M400".to_string());
+                self.show_message("Paused");
+                Ok(())
+            }
+            s => bail!("Cannot pause print in state {:?}", s),
+        }
+    }
+
+    /// run an action once the active print reaches the trigger of the hook
+    /// hooks whose layer or height is already printed are rejected
+    pub fn add_layer_hook(&self, hook: LayerHook) -> anyhow::Result<()> {
+        if self.job.is_active() {
+            let stats = self.job.stats.lock().unwrap();
+            let passed = match hook.trigger {
+                HookTrigger::Layer(l) => l < stats.layer_reached,
+                HookTrigger::Height(h) => h < stats.max_z,
+            };
+            if passed {
+                bail!("Layer hook {:?} is already passed", hook.trigger);
+            }
+        }
+        if !self.check_hook_policy(&hook.action) {
+            bail!(
+                "Layer hook {:?} is denied by the command policy",
+                hook.trigger
+            );
+        }
+
+        let mut hooks = self.job.hooks.lock().unwrap();
+        hooks.push(hook);
+        let _ = self
+            .tx
+            .send(Action::Telemetry(TelemetryData::LayerHooks(hooks.clone())));
+        Ok(())
+    }
+
+    /// stop printing a labeled object of the active print, the other objects are printed as before
+    /// moves of the object are not sent anymore, files labeled with M486 let the firmware skip it as well
+    pub fn cancel_object(&self, id: u32) -> anyhow::Result<()> {
+        if !self.job.is_active() {
+            bail!("No print job is active");
+        }
+
+        let objects = {
+            let mut objects = self.job.objects.lock().unwrap();
+            let object = objects
+                .iter_mut()
+                .find(|o| o.id == id)
+                .ok_or_else(|| anyhow!("Unknown object {}", id))?;
+            object.cancelled = true;
+            objects.clone()
+        };
+        if self.firmware_labels {
+            self.push_job_control(format!("M486 P{}", id));
+        }
+        let _ = self
+            .tx
+            .send(Action::Telemetry(TelemetryData::Objects(objects)));
+        Ok(())
+    }
+
+    /// remove all hooks which did not run yet
+    pub fn clear_layer_hooks(&self) {
+        self.job.hooks.lock().unwrap().clear();
+        let _ = self
+            .tx
+            .send(Action::Telemetry(TelemetryData::LayerHooks(vec![])));
+    }
+
+    /// resume a paused print
+    pub fn resume_print(&mut self) -> anyhow::Result<()> {
+        match self.job.state() {
+            Some(JobState::Pausing | JobState::Paused) => {
+                self.job.transition(JobState::Printing);
+                self.event.lock().unwrap().notify(42);
+                Ok(())
+            }
+            s => bail!("Cannot resume print in state {:?}", s),
+        }
+    }
+
+    /// set the profile of the connected printer
+    pub fn set_profile(&mut self, profile: PrinterProfile) {
+        *self.job.heater_limits.lock().unwrap() = profile.heaters;
+        self.profile = profile;
+    }
+
+    /// set the minimum time between two progress updates of the active print
+    pub fn set_progress_interval(&self, interval: Duration) {
+        *self.job.progress_interval.lock().unwrap() = interval;
+    }
+
+    /// choose a button of the dialog shown by the printer, the dialog is closed by the response
+    pub fn respond_to_prompt(&self, button: u8) -> anyhow::Result<()> {
+        let mut prompt = self.job.prompt.lock().unwrap();
+        let Some(p) = prompt.as_ref() else {
+            bail!("the printer shows no prompt");
+        };
+        if button as usize >= p.buttons.len() {
+            bail!(
+                "prompt has no button {}, it has {}",
+                button,
+                p.buttons.len()
+            );
+        }
+        *prompt = None;
+        self.push_job_control(format!("M876 S{}", button));
+        Ok(())
+    }
+
+    /// let a printer without display continue after the filament was changed
+    pub fn confirm_filament_change(&self) {
+        self.push_job_control("M108".to_string());
+    }
+
+    /// pack the sent lines with MeatPack if the firmware supports it
+    /// the result of the negotiation is sent with TelemetryData::MeatPack
+    pub fn set_meatpack(&self, enable: bool) {
+        let state = if enable { "on" } else { "off" };
+        self.push_manual(vec![format!("{} {}", HOST_CMD_MEATPACK, state)]);
+    }
+
+    /// check the reported temperatures and turn off the heaters or stop the printer if a check fails,
+    /// the temperature limits are taken from the printer profile
+    pub fn set_thermal_watchdog(&self, watchdog: Option<ThermalWatchdog>) {
+        *self.job.thermal_watchdog.lock().unwrap() = watchdog;
+    }
+
+    /// turn off the heaters after the printer was idle with heaters on for some time,
+    /// TelemetryData::IdleTimeoutWarning is sent shortly before
+    pub fn set_idle_timeout(&self, timeout: Option<IdleTimeout>) {
+        *self.job.idle_timeout.lock().unwrap() = timeout;
+    }
+
+    /// restart the idle timeout of the heaters
+    pub fn extend_idle_timeout(&self) {
+        self.push_manual(vec![format!("{} extend", HOST_CMD_IDLE)]);
+    }
+
+    /// choose which status updates are sent to the printer display while printing
+    pub fn set_display_updates(&self, display: DisplayUpdates) {
+        *self.job.display.lock().unwrap() = display;
+    }
+
+    /// use a json file at the given path to persist the print history
+    pub fn set_history_file(&mut self, path: impl Into<PathBuf>) -> anyhow::Result<()> {
+        self.history = Some(PrintHistory::open(path)?);
+        Ok(())
+    }
+
+    /// the print history if a history file is set
+    pub fn history(&self) -> Option<&PrintHistory> {
+        self.history.as_ref()
+    }
+
+    fn finish_print(&mut self, outcome: PrintOutcome) {
+        let f = match self.active_file.take() {
+            None => return,
+            Some(f) => f,
+        };
+        let stats = self.job.stats.lock().unwrap().clone();
+        self.job.hooks.lock().unwrap().clear();
+
+        let finished = FinishedPrint {
+            name: f.name,
+            size: f.size,
+            last_modified: f.last_modified,
+            start_time: f.start_time,
+            finish_time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis(),
+            outcome,
+            filament_used: stats.filament_used,
+            layer_reached: stats.layer_reached,
+            max_z: stats.max_z,
+            print_time: stats.print_time,
+        };
+        if let Some(history) = self.history.as_mut() {
+            if let Err(e) = history.record(finished.clone()) {
+                warn!("Failed to write print history: {}", e);
+            }
+        }
+        let _ = self
+            .tx
+            .send(Action::Telemetry(TelemetryData::PrintFinished(finished)));
+
+        self.handle_job_finished(outcome);
+    }
+
     async fn handle_action_commands(&mut self, mut rx: Receiver<Action>) {
-        while let Ok(v) = rx.recv().await {
-            match v {
-                Action::Telemetry(_) => {}
-                Action::StateChange(s) => {
-                    match s {
-                        PrinterStatus::Disconnected => {}
-                        PrinterStatus::Active => {}
-                        PrinterStatus::Idle => {
-                            if self.active_file.is_some() {
-                                let f = self.active_file.clone().unwrap();
+        loop {
+            let v = match rx.recv().await {
+                Ok(v) => v,
+                Err(RecvError::Lagged(n)) => {
+                    warn!("Action handler lagged behind, {} actions skipped", n);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
 
-                                let _ = self.tx.send(Action::Telemetry(
-                                    TelemetryData::PrintFinished(FinishedPrint {
-                                        name: f.name,
-                                        size: f.size,
-                                        last_modified: f.last_modified,
-                                        start_time: f.start_time,
-                                        finish_time: SystemTime::now()
-                                            .duration_since(UNIX_EPOCH)
-                                            .unwrap()
-                                            .as_millis(),
-                                    }),
-                                ));
-                                self.active_file = None;
-                            }
+            match v {
+                Action::Telemetry(TelemetryData::JobStateChange(s)) => {
+                    let outcome = match s {
+                        JobState::Completed => Some(PrintOutcome::Completed),
+                        JobState::Cancelled => Some(PrintOutcome::Cancelled),
+                        JobState::Failed { .. } => Some(PrintOutcome::Errored),
+                        _ => None,
+                    };
+                    if let Some(outcome) = outcome {
+                        self.finish_print(outcome);
+                    }
+                }
+                // the statistics are recorded if the host stops during the print
+                Action::Telemetry(TelemetryData::LayerChanged { .. }) => {
+                    let stats = self.job.stats.lock().unwrap().clone();
+                    if let Some(history) = self.history.as_mut() {
+                        if let Err(e) = history.save_stats(stats) {
+                            warn!("Failed to write print history: {}", e);
                         }
-                        PrinterStatus::Errored => {}
                     }
+                }
+                Action::Telemetry(_) => {}
+                Action::StateChange(s) => {
                     debug!("Printer State change: {}", s);
                 }
                 Action::PrinterAction(a) => match a {
@@ -166,48 +572,258 @@ impl GcodeSerial {
                         self.stop_print();
                     }
                     PrinterAction::Pause => {
-                        todo!()
+                        if let Err(e) = self.pause_print() {
+                            warn!("{}", e);
+                        }
                     }
                     PrinterAction::Resume => {
-                        todo!()
+                        if let Err(e) = self.resume_print() {
+                            warn!("{}", e);
+                        }
                     }
+                    // the printer stopped or continued by itself, the host follows
+                    PrinterAction::Paused => {
+                        if self.job.is_sending() {
+                            let _ = self.pause_print();
+                        }
+                    }
+                    PrinterAction::Resumed => {
+                        if self.job.state() == Some(JobState::Paused) {
+                            let _ = self.resume_print();
+                        }
+                    }
+                    PrinterAction::Start => {
+                        if !self.job_queue.is_empty() && !self.job.is_active() {
+                            if let Err(e) = self.start_next_job() {
+                                warn!("{}", e);
+                            }
+                        }
+                    }
+                    PrinterAction::OutOfFilament(_)
+                    | PrinterAction::Notification(_)
+                    | PrinterAction::ProbeFailed
+                    | PrinterAction::Prompt(_)
+                    | PrinterAction::PromptEnd => {}
                 },
                 Action::Command(c) => match c {
                     Command::SetTemps(b, c) => {
                         self.set_temps(b, c);
                     }
                     Command::StartPrint(n) => {
-                        self.start_print(n);
+                        if let Err(e) = self.start_print(n) {
+                            warn!("{}", e);
+                        }
+                    }
+                    Command::StartPrintForced(n) => {
+                        if let Err(e) = self.start_print_forced(n) {
+                            warn!("{}", e);
+                        }
+                    }
+                    Command::ValidateFile(path) => {
+                        match self.validate_file(&path) {
+                            Ok(report) => {
+                                let _ = self.tx.send(Action::Telemetry(
+                                    TelemetryData::ValidationReport(path, report),
+                                ));
+                            }
+                            Err(e) => warn!("Failed to validate {}: {}", path, e),
+                        }
                     }
                     Command::StopPrint => {
                         self.stop_print();
                     }
+                    Command::AddLayerHook(h) => {
+                        if let Err(e) = self.add_layer_hook(h) {
+                            warn!("{}", e);
+                        }
+                    }
+                    Command::CancelObject(id) => {
+                        if let Err(e) = self.cancel_object(id) {
+                            warn!("Failed to cancel object: {}", e);
+                        }
+                    }
+                    Command::ClearLayerHooks => {
+                        self.clear_layer_hooks();
+                    }
+                    Command::PausePrint => {
+                        if let Err(e) = self.pause_print() {
+                            warn!("{}", e);
+                        }
+                    }
+                    Command::ResumePrint => {
+                        if let Err(e) = self.resume_print() {
+                            warn!("{}", e);
+                        }
+                    }
+                    Command::SetSpeedFactor(p) => {
+                        self.set_speed_factor(p);
+                    }
+                    Command::SetFlowFactor(e, p) => {
+                        self.set_flow_factor(e, p);
+                    }
+                    Command::SetFanSpeed(f, s) => {
+                        self.set_fan_speed(f, s);
+                    }
+                    Command::BabyStep(z) => {
+                        self.babystep(z);
+                    }
+                    Command::Home(axes) => {
+                        if let Err(e) = self.home(&axes) {
+                            warn!("Failed to home: {}", e);
+                        }
+                    }
+                    Command::Jog(d, f) => {
+                        if let Err(e) = self.jog(&d, f) {
+                            warn!("Failed to jog: {}", e);
+                        }
+                    }
+                    Command::MoveTo(p, f) => {
+                        if let Err(e) = self.move_to(&p, f) {
+                            warn!("Failed to move: {}", e);
+                        }
+                    }
+                    Command::Extrude(l, f) => {
+                        if let Err(e) = self.extrude(l, f) {
+                            warn!("Failed to extrude: {}", e);
+                        }
+                    }
+                    Command::EnqueuePrint(n) => {
+                        if let Err(e) = self.enqueue_print(n) {
+                            warn!("Failed to enqueue print: {}", e);
+                        }
+                    }
+                    Command::RemoveJob(id) => {
+                        if let Err(e) = self.remove_job(id) {
+                            warn!("{}", e);
+                        }
+                    }
+                    Command::MoveJob(id, pos) => {
+                        if let Err(e) = self.move_job(id, pos) {
+                            warn!("{}", e);
+                        }
+                    }
+                    Command::StartNextJob => {
+                        if let Err(e) = self.start_next_job() {
+                            warn!("{}", e);
+                        }
+                    }
+                    Command::SetAutoStart(a) => {
+                        self.set_auto_start(a);
+                    }
+                    Command::SetDisplayUpdates(d) => {
+                        self.set_display_updates(d);
+                    }
+                    Command::SetMeatPack(enable) => self.set_meatpack(enable),
+                    Command::SetThermalWatchdog(w) => self.set_thermal_watchdog(w),
+                    Command::SetIdleTimeout(t) => self.set_idle_timeout(t),
+                    Command::ExtendIdleTimeout => self.extend_idle_timeout(),
+                    Command::RespondToPrompt(button) => {
+                        if let Err(e) = self.respond_to_prompt(button) {
+                            warn!("Failed to respond to prompt: {}", e);
+                        }
+                    }
+                    Command::ConfirmFilamentChange => self.confirm_filament_change(),
+                    Command::ListSdFiles => {
+                        if let Err(e) = self.list_sd_files() {
+                            warn!("Failed to list sd card files: {}", e);
+                        }
+                    }
+                    Command::StartSdPrint(name) => {
+                        if let Err(e) = self.start_sd_print(&name) {
+                            warn!("Failed to start sd card print: {}", e);
+                        }
+                    }
+                    Command::PauseSdPrint => {
+                        if let Err(e) = self.pause_sd_print() {
+                            warn!("Failed to pause sd card print: {}", e);
+                        }
+                    }
+                    Command::ResumeSdPrint => {
+                        if let Err(e) = self.resume_sd_print() {
+                            warn!("Failed to resume sd card print: {}", e);
+                        }
+                    }
+                    Command::StopSdPrint => {
+                        if let Err(e) = self.stop_sd_print() {
+                            warn!("Failed to stop sd card print: {}", e);
+                        }
+                    }
+                    Command::RequestSdProgress => {
+                        if let Err(e) = self.request_sd_progress() {
+                            warn!("Failed to request sd card progress: {}", e);
+                        }
+                    }
+                    Command::DeleteSdFile(name) => {
+                        if let Err(e) = self.delete_sd_file(&name) {
+                            warn!("Failed to delete sd card file: {}", e);
+                        }
+                    }
+                    Command::UploadToSd(path, name) => {
+                        if let Err(e) = self.upload_to_sd(&path, &name) {
+                            warn!("Failed to upload {} to the sd card: {}", path, e);
+                        }
+                    }
+                    Command::ConfirmBedClear => {
+                        self.confirm_bed_clear();
+                    }
+                    Command::RequestHistory(n) => {
+                        let entries = self.history.as_ref().map(|h| h.last(n)).unwrap_or_default();
+                        let _ = self
+                            .tx
+                            .send(Action::Telemetry(TelemetryData::History(entries)));
+                    }
+                    Command::RequestThumbnails(path) => match extract_thumbnails(&path) {
+                        Ok(t) => {
+                            let _ = self
+                                .tx
+                                .send(Action::Telemetry(TelemetryData::Thumbnails(path, t)));
+                        }
+                        Err(e) => warn!("Failed to read thumbnails of {}: {}", path, e),
+                    },
+                    Command::DisableMotors => {
+                        if let Err(e) = self.disable_motors() {
+                            warn!("Failed to disable motors: {}", e);
+                        }
+                    }
                 },
             }
         }
     }
 
-    fn load_file_to_que(&self, reader: BufReader<File>) {
-        for line in reader.lines() {
-            let mut command = line.unwrap();
-
-            // match for max_layer_z commet to get max layyer height - might be prusaslicer only!
-            match RE_MAX_Z_POS.captures(command.as_str()) {
-                None => {}
-                Some(c) => {
-                    let h1: f32 = c
-                        .get(1)
-                        .map_or("0.0", |m| m.as_str())
-                        .parse()
-                        .unwrap_or(0.0);
-                    let _ = self
-                        .tx
-                        .send(Action::Telemetry(TelemetryData::MaxZHeight(h1)));
-                }
+    fn load_file_to_que(
+        &mut self,
+        mut reader: impl BufRead,
+        validator: &mut GcodeValidator,
+    ) -> anyhow::Result<GcodeMetadata> {
+        let mut parser = MetadataParser::new();
+        let mut layers = LayerDetector::default();
+        let mut objects = ObjectDetector::default();
+        let mut estimator = TimeEstimator::new(self.profile.motion.clone());
+        let mut commands: Vec<String> = Vec::new();
+        let mut positions: Vec<LinePosition> = Vec::new();
+        let mut bytes = 0;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            // the bytes of the line ending are counted as they are in the file
+            let n = reader.read_line(&mut line)?;
+            if n == 0 {
+                break;
             }
+            bytes += n as u64;
+            let mut command = line.trim_end_matches(['\r', '\n']).to_string();
+
+            // slicer comments contain the metadata of the file
+            parser.parse_line(command.as_str());
+            layers.parse_line(command.as_str(), commands.len());
+            objects.parse_line(command.as_str());
+            validator.add_line(command.as_str());
 
-            // if line starts with ; or is empty we skip it
-            if command.trim().starts_with(';') || command.trim().is_empty() {
+            // if line starts with ; or is empty we skip it, object labels of klipper are not sent either
+            if command.trim().starts_with(';')
+                || command.trim().is_empty()
+                || command.trim().starts_with("EXCLUDE_OBJECT_")
+            {
                 continue;
             }
 
@@ -216,10 +832,103 @@ impl GcodeSerial {
                 command = command.trim().split(';').collect::<Vec<&str>>()[0].to_string();
             }
 
-            self.que.lock().unwrap().push_back(command);
+            positions.push(LinePosition {
+                bytes,
+                line: positions.len() as u32 + 1,
+                time: estimator.add_line(command.as_str()),
+                object: objects.current(),
+            });
+            commands.push(command);
+        }
+        if let Some(last) = positions.last_mut() {
+            last.time = estimator.finish();
+            // trailing comments are read as well, the total is the size of the file
+            last.bytes = bytes;
+        }
+
+        let (objects, firmware_labels) = objects.finish();
+        *self.job.objects.lock().unwrap() = objects;
+        self.firmware_labels = firmware_labels;
+
+        // layer changes are queued as host commands in front of the first line of each layer
+        let layers = layers.finish();
+        let total = layers.len();
+        let mut layers = layers.into_iter().enumerate().peekable();
+
+        let mut lines = self.job.lines.lock().unwrap();
+        let mut line_positions = self.job.positions.lock().unwrap();
+        lines.clear();
+        line_positions.clear();
+        let mut position = LinePosition::default();
+        for (i, command) in commands.into_iter().enumerate() {
+            while let Some((index, (_, z))) = layers.next_if(|(_, (pos, _))| *pos == i) {
+                lines.push_back(format!("{} {} {} {}", HOST_CMD_LAYER, index, total, z));
+                line_positions.push(position);
+            }
+            position = positions[i];
+            lines.push_back(command);
+            line_positions.push(position);
+        }
+        Ok(parser.finish())
+    }
+
+    fn update_status(&self, s: PrinterStatus) {
+        if *self.printer_status.lock().unwrap() != s {
+            *self.printer_status.lock().unwrap() = s.clone();
+            let _ = self.tx.send(Action::StateChange(s));
+        }
+    }
+
+    /// manual commands are not allowed to interfere with a running print
+    fn ensure_no_active_job(&self) -> anyhow::Result<()> {
+        if self.active_file.is_some() {
+            bail!("a print job is active");
+        }
+        if self.job.sd_printing.load(Ordering::Relaxed) {
+            bail!("an sd card print is active");
+        }
+        Ok(())
+    }
+
+    /// append manual commands to the que, none is sent if the policy denies one of them
+    fn push_manual(&self, cmds: Vec<String>) -> bool {
+        if !cmds.iter().all(|c| self.check_policy(c, true)) {
+            return false;
+        }
+        self.que.lock().unwrap().extend(cmds);
+        self.event.lock().unwrap().notify(42);
+        true
+    }
+
+    /// show a message on the printer display if display messages are enabled
+    fn show_message(&self, msg: &str) {
+        if self.job.display.lock().unwrap().messages {
+            self.que.lock().unwrap().push_back(display_message_cmd(msg));
+            self.event.lock().unwrap().notify(42);
         }
     }
 
+    /// push a command in front of the que so it is sent before pending print lines,
+    /// returns false if the policy denies it
+    fn push_priority(&self, cmd: String) -> bool {
+        if !self.check_policy(&cmd, true) {
+            return false;
+        }
+        self.que.lock().unwrap().push_front(cmd);
+        self.event.lock().unwrap().notify(42);
+        true
+    }
+
+    /// push a command controlling the active job in front of the que,
+    /// it is not blocked by the policy during the job
+    fn push_job_control(&self, cmd: String) {
+        if !self.check_policy(&cmd, false) {
+            return;
+        }
+        self.que.lock().unwrap().push_front(cmd);
+        self.event.lock().unwrap().notify(42);
+    }
+
     fn clear_que_add_ending_code(&self) {
         let mut que = self.que.lock().unwrap();
         que.clear();
@@ -235,3 +944,30 @@ impl GcodeSerial {
                                           // que.push_back("M603".to_string()); // prusa specific gcode-endprint
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::action::HostPrompt;
+    use tokio::sync::broadcast;
+
+    #[test]
+    fn respond_to_prompt_checks_the_buttons() {
+        let (tx, _rx) = broadcast::channel(64);
+        let gs = GcodeSerial::new(tx);
+        assert!(gs.respond_to_prompt(0).is_err());
+
+        *gs.job.prompt.lock().unwrap() = Some(HostPrompt {
+            message: "Filament runout".to_string(),
+            buttons: vec!["Purge more".to_string(), "Continue".to_string()],
+        });
+        assert!(gs.respond_to_prompt(2).is_err());
+        gs.respond_to_prompt(1).unwrap();
+        assert_eq!(
+            gs.que.lock().unwrap().front().map(|c| c.as_str()),
+            Some("M876 S1")
+        );
+        // the dialog is closed by the response
+        assert!(gs.respond_to_prompt(1).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::gcode_serial::GcodeSerial;
+use crate::models::action::{Action, TelemetryData};
+use crate::models::file::PrintOutcome;
+use crate::models::job::{AutoStart, QueuedJob};
+use anyhow::anyhow;
+use log::{info, warn};
+use std::fs;
+use std::sync::atomic::Ordering;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+impl GcodeSerial {
+    /// add a gcode file to the job queue and return its job id
+    /// the job is started right away if auto start is enabled and the printer is free
+    pub fn enqueue_print(&mut self, file_path: String) -> anyhow::Result<u32> {
+        let metadata = fs::metadata(&file_path)?;
+
+        let id = self.next_job_id;
+        self.next_job_id += 1;
+
+        self.job_queue.push_back(QueuedJob {
+            id,
+            name: file_path,
+            size: metadata.len(),
+            last_modified: metadata.modified()?.duration_since(UNIX_EPOCH)?.as_millis(),
+            added_time: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
+        });
+        self.send_job_queue();
+
+        if self.auto_start != AutoStart::Off
+            && self.active_file.is_none()
+            && !self.job.sd_printing.load(Ordering::Relaxed)
+            && !self.awaiting_bed_clear
+        {
+            self.start_next_job()?;
+        }
+        Ok(id)
+    }
+
+    /// remove a pending job from the job queue
+    pub fn remove_job(&mut self, id: u32) -> anyhow::Result<()> {
+        let pos = self.job_position(id)?;
+        self.job_queue.remove(pos);
+        self.send_job_queue();
+        Ok(())
+    }
+
+    /// move a pending job to a new position, positions out of range move it to the end
+    pub fn move_job(&mut self, id: u32, position: usize) -> anyhow::Result<()> {
+        let pos = self.job_position(id)?;
+        let job = self.job_queue.remove(pos).unwrap();
+        let position = position.min(self.job_queue.len());
+        self.job_queue.insert(position, job);
+        self.send_job_queue();
+        Ok(())
+    }
+
+    /// pending jobs of the job queue
+    pub fn pending_jobs(&self) -> Vec<QueuedJob> {
+        self.job_queue.iter().cloned().collect()
+    }
+
+    /// start the first pending job of the job queue,
+    /// jobs which fail to start are removed and the next one is tried
+    pub fn start_next_job(&mut self) -> anyhow::Result<()> {
+        // the jobs stay pending while the printer is busy
+        self.ensure_no_active_job()?;
+        self.ensure_no_upload()?;
+
+        loop {
+            let job = self
+                .job_queue
+                .pop_front()
+                .ok_or_else(|| anyhow!("Job queue is empty"))?;
+
+            info!("Starting job {}: {}", job.id, job.name);
+            let result = self.start_print(job.name.clone());
+            self.send_job_queue();
+            match result {
+                Ok(()) => {
+                    self.awaiting_bed_clear = false;
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!("Job {} failed to start: {}", job.id, e);
+                    let _ = self.tx.send(Action::Telemetry(TelemetryData::JobFailed {
+                        id: job.id,
+                        name: job.name,
+                        error: e.to_string(),
+                    }));
+                }
+            }
+        }
+    }
+
+    /// set what happens when a print finished
+    pub fn set_auto_start(&mut self, auto_start: AutoStart) {
+        self.auto_start = auto_start;
+    }
+
+    /// confirm that the bed is cleared so the next job can start
+    pub fn confirm_bed_clear(&mut self) {
+        self.awaiting_bed_clear = false;
+        if self.auto_start != AutoStart::Off && !self.job_queue.is_empty() {
+            if let Err(e) = self.start_next_job() {
+                warn!("{}", e);
+            }
+        }
+    }
+
+    pub(crate) fn handle_job_finished(&mut self, outcome: PrintOutcome) {
+        // a failed or cancelled print always needs manual interaction before the next job
+        if outcome != PrintOutcome::Completed {
+            return;
+        }
+
+        match self.auto_start {
+            AutoStart::Off => {}
+            AutoStart::Immediate => {
+                if !self.job_queue.is_empty() {
+                    if let Err(e) = self.start_next_job() {
+                        warn!("{}", e);
+                    }
+                }
+            }
+            AutoStart::AfterBedClear => {
+                self.awaiting_bed_clear = true;
+                if !self.job_queue.is_empty() {
+                    let _ = self
+                        .tx
+                        .send(Action::Telemetry(TelemetryData::AwaitingBedClear));
+                }
+            }
+        }
+    }
+
+    fn job_position(&self, id: u32) -> anyhow::Result<usize> {
+        self.job_queue
+            .iter()
+            .position(|j| j.id == id)
+            .ok_or_else(|| anyhow!("No pending job with id {}", id))
+    }
+
+    fn send_job_queue(&self) {
+        let _ = self.tx.send(Action::Telemetry(TelemetryData::JobQueue(
+            self.pending_jobs(),
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::broadcast;
+
+    #[test]
+    fn job_failing_to_start_is_skipped() {
+        let (tx, mut rx) = broadcast::channel(64);
+        let mut gs = GcodeSerial::new(tx);
+        let dir = std::env::temp_dir().join(format!("gcode-serial-jobs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let missing = dir.join("missing.gcode").to_string_lossy().to_string();
+        let cube = dir.join("cube.gcode").to_string_lossy().to_string();
+        fs::write(&missing, "G28\n").unwrap();
+        fs::write(&cube, "G28\nG1 X10\n").unwrap();
+
+        let failing = gs.enqueue_print(missing.clone()).unwrap();
+        gs.enqueue_print(cube.clone()).unwrap();
+        fs::remove_file(&missing).unwrap();
+
+        gs.start_next_job().unwrap();
+        assert!(gs.pending_jobs().is_empty());
+        assert_eq!(
+            gs.active_file.as_ref().map(|f| f.name.as_str()),
+            Some(cube.as_str())
+        );
+
+        let mut failed = vec![];
+        while let Ok(action) = rx.try_recv() {
+            if let Action::Telemetry(TelemetryData::JobFailed { id, name, .. }) = action {
+                failed.push((id, name));
+            }
+        }
+        assert_eq!(failed, vec![(failing, missing)]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
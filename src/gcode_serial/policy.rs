@@ -0,0 +1,176 @@
+use crate::gcode_serial::GcodeSerial;
+use crate::models::action::{Action, TelemetryData};
+use crate::models::job::{LayerAction, LayerHook};
+use crate::models::policy::CommandPolicy;
+use crate::serial::host_command::HOST_CMD_PREFIX;
+use lazy_static::lazy_static;
+use log::warn;
+use regex::Regex;
+
+lazy_static! {
+    // M104 S210 / M104S210 / G29.1 / T0, the parameters may follow without space
+    static ref RE_CMD_WORD: Regex = Regex::new(r"(?i)^([GMT]\d+(?:\.\d+)?)(.*)$").unwrap();
+    // S210 / R200 / S 60
+    static ref RE_TEMP_PARAM: Regex = Regex::new(r"(?i)[SR]\s*(-?\d*\.?\d+)").unwrap();
+}
+
+impl GcodeSerial {
+    /// restrict the commands of users and the lines of printed files, denied commands are not sent
+    /// and reported with TelemetryData::CommandDenied
+    /// the policy can't be changed with a Command, every subscriber of the channel could send it
+    pub fn set_command_policy(&mut self, policy: CommandPolicy) {
+        self.policy = policy;
+
+        // hooks added before are removed if the new policy denies them
+        let hooks: Vec<LayerHook> = self.job.hooks.lock().unwrap().drain(..).collect();
+        let hooks: Vec<LayerHook> = hooks
+            .into_iter()
+            .filter(|h| self.check_hook_policy(&h.action))
+            .collect();
+        *self.job.hooks.lock().unwrap() = hooks.clone();
+        let _ = self
+            .tx
+            .send(Action::Telemetry(TelemetryData::LayerHooks(hooks)));
+    }
+
+    /// hook lines are queued without further checks when the layer is reached, scripts count as manual commands
+    pub(crate) fn check_hook_policy(&self, action: &LayerAction) -> bool {
+        match action {
+            LayerAction::Pause => true,
+            LayerAction::FilamentChange => self.check_policy("M600", false),
+            LayerAction::Script(lines) => lines.iter().all(|l| self.check_policy(l.trim(), true)),
+        }
+    }
+
+    /// check a command against the policy and report it if it is denied,
+    /// manual commands are also rejected during a job if the policy blocks them
+    pub(crate) fn check_policy(&self, cmd: &str, manual: bool) -> bool {
+        let Some(reason) = self.policy_violation(cmd, manual) else {
+            return true;
+        };
+        warn!("Command {} denied: {}", cmd, reason);
+        let _ = self
+            .tx
+            .send(Action::Telemetry(TelemetryData::CommandDenied {
+                command: cmd.to_string(),
+                reason,
+            }));
+        false
+    }
+
+    fn policy_violation(&self, cmd: &str, manual: bool) -> Option<String> {
+        if manual
+            && self.policy.block_manual_during_job
+            && self.job.is_active()
+            && !cmd.starts_with(HOST_CMD_PREFIX)
+        {
+            return Some("manual commands are blocked during a print job".to_string());
+        }
+        self.policy.violation(cmd)
+    }
+}
+
+impl CommandPolicy {
+    /// reason why the policy denies the command
+    pub(crate) fn violation(&self, cmd: &str) -> Option<String> {
+        // host commands are handled by the lib and never reach the printer
+        if cmd.starts_with(HOST_CMD_PREFIX) {
+            return None;
+        }
+
+        let policy = self;
+        // other lines like klipper macros are compared by their first word
+        let (word, params) = match RE_CMD_WORD.captures(cmd) {
+            Some(c) => (c[1].to_uppercase(), c.get(2).map_or("", |m| m.as_str())),
+            None => (
+                cmd.split_whitespace().next().unwrap_or("").to_uppercase(),
+                "",
+            ),
+        };
+        if policy.deny.iter().any(|d| d.eq_ignore_ascii_case(&word)) {
+            return Some(format!("{} is denied", word));
+        }
+        if !policy.allow.is_empty() && !policy.allow.iter().any(|a| a.eq_ignore_ascii_case(&word)) {
+            return Some(format!("{} is not allowed", word));
+        }
+
+        let max = match word.as_str() {
+            "M104" | "M109" => policy.max_extruder_temp,
+            "M140" | "M190" => policy.max_bed_temp,
+            _ => None,
+        };
+        if let (Some(max), Some(c)) = (max, RE_TEMP_PARAM.captures(params)) {
+            let temp: f32 = c[1].parse().unwrap_or(0.0);
+            if temp > max {
+                return Some(format!(
+                    "temperature {}°C is above the limit of {}°C",
+                    temp, max
+                ));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> CommandPolicy {
+        CommandPolicy {
+            deny: vec!["M500".to_string(), "M502".to_string()],
+            max_extruder_temp: Some(250.0),
+            max_bed_temp: Some(100.0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn deny_list() {
+        let p = policy();
+        assert!(p.violation("M500").is_some());
+        assert!(p.violation("m502 ; reset").is_some());
+        assert!(p.violation("M5000").is_none());
+        assert!(p.violation("G28").is_none());
+    }
+
+    #[test]
+    fn deny_list_without_space() {
+        let p = policy();
+        assert!(p.violation("M500S0").is_some());
+        assert!(p.violation("M502T0").is_some());
+    }
+
+    #[test]
+    fn temperature_limits() {
+        let p = policy();
+        assert!(p.violation("M104 S300").is_some());
+        assert!(p.violation("M109 R260").is_some());
+        assert!(p.violation("M190 S110").is_some());
+        assert!(p.violation("M104 T0 S200").is_none());
+        assert!(p.violation("M140 S60").is_none());
+    }
+
+    #[test]
+    fn temperature_limits_without_space() {
+        let p = policy();
+        assert!(p.violation("M104S300").is_some());
+        assert!(p.violation("M140S110").is_some());
+        assert!(p.violation("M109T0S260").is_some());
+        assert!(p.violation("M104S200").is_none());
+    }
+
+    #[test]
+    fn allow_list() {
+        let p = CommandPolicy {
+            allow: vec!["G28".to_string(), "G1".to_string()],
+            ..Default::default()
+        };
+        assert!(p.violation("G1X10Y10").is_none());
+        assert!(p.violation("G28").is_none());
+        assert!(p.violation("G10").is_some());
+        assert!(p.violation("M84").is_some());
+        // host commands never reach the printer
+        assert!(p.violation("@pause").is_none());
+    }
+}
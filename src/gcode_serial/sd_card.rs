@@ -0,0 +1,117 @@
+use crate::gcode::bgcode::open_gcode;
+use crate::gcode_serial::GcodeSerial;
+use crate::models::action::{Action, PrinterStatus, TelemetryData};
+use crate::serial::sd_card::SdUpload;
+use anyhow::bail;
+use log::info;
+use std::io::BufRead;
+use std::sync::atomic::Ordering;
+
+impl GcodeSerial {
+    /// list the files of the sd card with long names and sizes, answered with TelemetryData::SdFiles
+    pub fn list_sd_files(&self) -> anyhow::Result<()> {
+        self.ensure_no_upload()?;
+        self.push_manual(vec!["M20 L".to_string()]);
+        Ok(())
+    }
+
+    /// select a file of the sd card and start printing it
+    pub fn start_sd_print(&mut self, name: &str) -> anyhow::Result<()> {
+        self.ensure_no_active_job()?;
+        self.ensure_no_upload()?;
+        info!("Starting sd card print of {}", name);
+        // set before the commands are queued, the drained que must not reset the status
+        self.job.sd_printing.store(true, Ordering::Relaxed);
+        if !self.push_manual(vec![format!("M23 {}", name), "M24".to_string()]) {
+            self.job.sd_printing.store(false, Ordering::Relaxed);
+            bail!("Sd card print of {} is denied by the command policy", name);
+        }
+        self.update_status(PrinterStatus::Active);
+        Ok(())
+    }
+
+    /// abort the sd card print, the printer closes the file without running the rest of it
+    pub fn stop_sd_print(&mut self) -> anyhow::Result<()> {
+        self.ensure_no_upload()?;
+        if !self.job.sd_printing.load(Ordering::Relaxed) {
+            bail!("no sd card print is active");
+        }
+        if !self.push_priority("M524".to_string()) {
+            bail!("Stopping the sd card print is denied by the command policy");
+        }
+        info!("Stopping sd card print");
+        self.job.sd_printing.store(false, Ordering::Relaxed);
+        self.update_status(PrinterStatus::Idle);
+        Ok(())
+    }
+
+    pub fn pause_sd_print(&self) -> anyhow::Result<()> {
+        self.ensure_no_upload()?;
+        if !self.push_priority("M25".to_string()) {
+            bail!("Pausing the sd card print is denied by the command policy");
+        }
+        Ok(())
+    }
+
+    pub fn resume_sd_print(&self) -> anyhow::Result<()> {
+        self.ensure_no_upload()?;
+        self.push_manual(vec!["M24".to_string()]);
+        Ok(())
+    }
+
+    /// request the progress and the file of the sd card print
+    pub fn request_sd_progress(&self) -> anyhow::Result<()> {
+        self.ensure_no_upload()?;
+        self.push_manual(vec!["M27".to_string(), "M27 C".to_string()]);
+        Ok(())
+    }
+
+    pub fn delete_sd_file(&self, name: &str) -> anyhow::Result<()> {
+        self.ensure_no_upload()?;
+        self.push_manual(vec![format!("M30 {}", name)]);
+        Ok(())
+    }
+
+    /// write a local gcode file to the sd card, comments and empty lines are not uploaded
+    /// progress is sent with TelemetryData::SdUploadProgress
+    pub fn upload_to_sd(&self, file_path: &str, name: &str) -> anyhow::Result<()> {
+        self.ensure_no_active_job()?;
+        self.ensure_no_upload()?;
+
+        let mut lines = vec![];
+        let mut validator = self.validator();
+        for line in open_gcode(file_path)?.lines() {
+            let line = line?;
+            validator.add_line(&line);
+            let cmd = line.split(';').next().unwrap_or("").trim();
+            if !cmd.is_empty() {
+                lines.push(cmd.to_string());
+            }
+        }
+
+        // the printer runs the file later, only the command policy is enforced here
+        let report = validator.finish();
+        if report.is_denied() {
+            let _ = self
+                .tx
+                .send(Action::Telemetry(TelemetryData::ValidationReport(
+                    file_path.to_string(),
+                    report,
+                )));
+            bail!("File {} is denied by the command policy", file_path);
+        }
+
+        info!("Uploading {} to the sd card as {}", file_path, name);
+        *self.sd_upload.lock().unwrap() = Some(SdUpload::new(name.to_string(), lines));
+        self.event.lock().unwrap().notify(42);
+        Ok(())
+    }
+
+    /// commands sent during an upload would be written into the file
+    pub(crate) fn ensure_no_upload(&self) -> anyhow::Result<()> {
+        if self.sd_upload.lock().unwrap().is_some() {
+            bail!("an upload to the sd card is running");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use crate::models::file::{FinishedPrint, GcodeFile, JobStats, PrintOutcome};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize, Default)]
+struct HistoryFile {
+    active: Option<GcodeFile>,
+    /// statistics of the active print saved during the print
+    #[serde(default)]
+    active_stats: Option<JobStats>,
+    entries: Vec<FinishedPrint>,
+}
+
+/// Print history persisted to a local json file
+pub struct PrintHistory {
+    path: PathBuf,
+    data: HistoryFile,
+}
+
+impl PrintHistory {
+    /// open or create the history file at the given path
+    /// a print still marked as active is recorded as power loss
+    pub fn open(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let data = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            HistoryFile::default()
+        };
+
+        let mut history = PrintHistory { path, data };
+        if let Some(f) = history.data.active.take() {
+            warn!("Print of {} was interrupted, recording power loss", f.name);
+            let stats = history.data.active_stats.take().unwrap_or_default();
+            history.data.entries.push(FinishedPrint {
+                name: f.name,
+                size: f.size,
+                last_modified: f.last_modified,
+                start_time: f.start_time,
+                // the real end is unknown, use the time the loss was detected
+                finish_time: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
+                outcome: PrintOutcome::PowerLoss,
+                filament_used: stats.filament_used,
+                layer_reached: stats.layer_reached,
+                max_z: stats.max_z,
+                print_time: stats.print_time,
+            });
+            history.save()?;
+        }
+        Ok(history)
+    }
+
+    /// all recorded prints, oldest first
+    pub fn entries(&self) -> &[FinishedPrint] {
+        &self.data.entries
+    }
+
+    /// the last n recorded prints, newest first
+    pub fn last(&self, n: usize) -> Vec<FinishedPrint> {
+        self.data.entries.iter().rev().take(n).cloned().collect()
+    }
+
+    /// recorded prints with the given outcome, oldest first
+    pub fn with_outcome(&self, outcome: PrintOutcome) -> Vec<FinishedPrint> {
+        self.data
+            .entries
+            .iter()
+            .filter(|e| e.outcome == outcome)
+            .cloned()
+            .collect()
+    }
+
+    /// remove all recorded prints
+    pub fn clear(&mut self) -> anyhow::Result<()> {
+        self.data.entries.clear();
+        self.save()
+    }
+
+    pub(crate) fn set_active(&mut self, file: Option<GcodeFile>) -> anyhow::Result<()> {
+        self.data.active = file;
+        self.data.active_stats = None;
+        self.save()
+    }
+
+    /// save the statistics of the active print, they are recorded if the print is interrupted
+    pub(crate) fn save_stats(&mut self, stats: JobStats) -> anyhow::Result<()> {
+        if self.data.active.is_none() {
+            return Ok(());
+        }
+        self.data.active_stats = Some(stats);
+        self.save()
+    }
+
+    pub(crate) fn record(&mut self, print: FinishedPrint) -> anyhow::Result<()> {
+        self.data.active = None;
+        self.data.active_stats = None;
+        self.data.entries.push(print);
+        self.save()
+    }
+
+    /// write a temporary file and rename it, an interrupted write keeps the previous history
+    fn save(&self) -> anyhow::Result<()> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, serde_json::to_string(&self.data)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interrupted_print_keeps_saved_stats() {
+        let path = std::env::temp_dir().join(format!("history-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut history = PrintHistory::open(&path).unwrap();
+        history
+            .set_active(Some(GcodeFile {
+                name: "cube.gcode".to_string(),
+                size: 100,
+                last_modified: 0,
+                start_time: 0,
+                metadata: Default::default(),
+            }))
+            .unwrap();
+        history
+            .save_stats(JobStats {
+                filament_used: 120.5,
+                layer_reached: 12,
+                max_z: 2.4,
+                print_time: 300.0,
+            })
+            .unwrap();
+        drop(history);
+
+        let history = PrintHistory::open(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        let entry = &history.entries()[0];
+        assert_eq!(entry.outcome, PrintOutcome::PowerLoss);
+        assert_eq!(entry.layer_reached, 12);
+        assert_eq!(entry.filament_used, 120.5);
+        assert_eq!(entry.max_z, 2.4);
+    }
+}
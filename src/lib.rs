@@ -1,4 +1,6 @@
+pub mod gcode;
 pub mod gcode_serial;
+pub mod history;
 pub mod models;
 
 mod serial;
@@ -1,5 +1,13 @@
 use crate::models::file::{FinishedPrint, GcodeFile};
-use crate::models::temperature::Temperature;
+use crate::models::firmware_error::FirmwareError;
+use crate::models::job::{
+    AutoStart, DisplayUpdates, JobProgress, JobState, LayerHook, PrintObject, QueuedJob,
+};
+use crate::models::metadata::Thumbnail;
+use crate::models::motion::{Axis, Coordinates};
+use crate::models::sd_card::{SdFile, SdUploadProgress};
+use crate::models::temperature::{IdleTimeout, Temperature, ThermalAlarm, ThermalWatchdog};
+use crate::models::validation::ValidationReport;
 use serde::{Deserialize, Serialize};
 use strum::Display;
 
@@ -25,23 +33,84 @@ pub enum PrinterStatus {
 }
 
 /// Action sent by printer
-#[derive(Clone, Serialize, Deserialize, Display)]
+#[derive(Clone, Serialize, Deserialize, Display, Debug)]
 pub enum PrinterAction {
     Cancel,
     Pause,
     Resume,
+    /// the printer paused the print by itself
+    Paused,
+    /// the printer resumed the print by itself
+    Resumed,
+    /// filament runout sensor triggered (extruder if reported)
+    OutOfFilament(Option<u8>),
+    /// message of the firmware for the user
+    Notification(String),
+    ProbeFailed,
+    /// print start selected on the printer
+    Start,
+    /// the printer shows a dialog and waits for RespondToPrompt
+    Prompt(HostPrompt),
+    /// the dialog of the printer was closed
+    PromptEnd,
+}
+
+/// Dialog of the printer waiting for a button to be chosen by the host
+#[derive(Clone, Serialize, Deserialize, Default, Debug)]
+pub struct HostPrompt {
+    pub message: String,
+    pub buttons: Vec<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub enum TelemetryData {
     /// Hotend and Bed Temperature telemtry
     Temps(Temperature),
-    /// commands left in Que
-    Progress(u32),
     /// Currently only used by sd-card prints (percentage completed)
     PercentDone(u32),
     /// Currently only used by sd-card prints (Minutes left to print)
     MinsRemaining(u32),
+    /// Progress of the active print by bytes, lines and estimated time
+    JobProgress(JobProgress),
+    /// Files on the sd card of the printer
+    SdFiles(Vec<SdFile>),
+    /// Progress of the sd card print
+    SdPrintProgress {
+        bytes_printed: u64,
+        bytes_total: u64,
+    },
+    /// File of the sd card print, None if the printer is not printing from sd card
+    SdCurrentFile(Option<String>),
+    /// Progress of a file upload to the sd card
+    SdUploadProgress(SdUploadProgress),
+    /// Upload to the sd card finished, error is set if it failed
+    SdUploadFinished { name: String, error: Option<String> },
+    /// The printer reported a failed sd card operation
+    SdError(String),
+    /// The printer waits for a filament change (extruder if reported)
+    FilamentChangeRequired(Option<u8>),
+    /// The printer continues after a filament change
+    FilamentChangeFinished,
+    /// Labeled objects of the active print with their cancel state
+    Objects(Vec<PrintObject>),
+    /// The printer restarted, the active print failed and the connection is initialized again
+    PrinterRestarted,
+    /// A check of the thermal watchdog failed, the heaters were turned off or the printer was stopped
+    ThermalAlarm(ThermalAlarm),
+    /// The heaters are turned off because of the idle timeout in the given seconds
+    IdleTimeoutWarning(u32),
+    /// The heaters were turned off because the printer was idle
+    IdleTimeoutExpired,
+    /// The firmware reported an error, fatal errors end the active print
+    FirmwareError(FirmwareError),
+    /// MeatPack state of the connection and the bytes the sent lines had without packing
+    MeatPack {
+        active: bool,
+        bytes_plain: u64,
+        bytes_sent: u64,
+    },
+    /// Print time of the active print so far and simulated time left, corrected by the real print speed (seconds)
+    TimeEstimate { elapsed: u32, remaining: u32 },
     /// total number of commands of active print
     TotalCommandCount(u32),
     /// target extruder temp changed
@@ -54,10 +123,42 @@ pub enum TelemetryData {
     MaxZHeight(f32),
     /// Fan speed changed
     FanSpeed(f32),
+    /// Fan speed of a specific fan changed (fan index, speed 0.0-1.0)
+    IndexedFanSpeed(u8, f32),
+    /// Feedrate override changed (percent)
+    SpeedFactor(u32),
+    /// Flow override of an extruder changed (extruder index, percent)
+    FlowFactor(u8, u32),
+    /// Accumulated babystep z offset changed (mm)
+    BabyStepZ(f32),
     /// Active print file changed either file or none
-    ActiveFileChange(Option<GcodeFile>),
+    ActiveFileChange(Option<Box<GcodeFile>>),
     /// Finished print event
     PrintFinished(FinishedPrint),
+    /// Lifecycle state of the active print job changed
+    JobStateChange(JobState),
+    /// The active print started a new layer (0 based index, number of layers, z height)
+    LayerChanged { index: u32, total: u32, z: f32 },
+    /// Layer hooks waiting to run changed
+    LayerHooks(Vec<LayerHook>),
+    /// Pending jobs of the job queue changed
+    JobQueue(Vec<QueuedJob>),
+    /// A job of the job queue could not be started and was removed from it
+    JobFailed {
+        id: u32,
+        name: String,
+        error: String,
+    },
+    /// Next job waits for the confirmation that the bed is cleared
+    AwaitingBedClear,
+    /// Requested entries of the print history, newest first
+    History(Vec<FinishedPrint>),
+    /// Requested thumbnails of a file (file path, thumbnails)
+    Thumbnails(String, Vec<Thumbnail>),
+    /// A command of a user or a line of a file was not sent because of the command policy
+    CommandDenied { command: String, reason: String },
+    /// Issues found by checking a file against the printer profile (file path, report)
+    ValidationReport(String, ValidationReport),
 }
 
 /// Send an Action command to the lib
@@ -65,8 +166,84 @@ pub enum TelemetryData {
 pub enum Command {
     /// Set target temps (bed, extruder)
     SetTemps(u16, u16),
-    /// Start printing a file given by path
+    /// Start printing a file given by path, files with validation errors are refused
     StartPrint(String),
+    /// Start printing a file given by path even if it fails the validation
+    StartPrintForced(String),
+    /// Check a file given by path against the printer profile, answered with ValidationReport
+    ValidateFile(String),
     /// stop currently active print
     StopPrint,
+    /// pause the active print
+    PausePrint,
+    /// resume the paused print
+    ResumePrint,
+    /// Run an action (pause, filament change or script) when the active print reaches a layer or height
+    AddLayerHook(LayerHook),
+    /// Remove all layer hooks which did not run yet
+    ClearLayerHooks,
+    /// Set feedrate override in percent (M220)
+    SetSpeedFactor(u32),
+    /// Set flow override in percent for an extruder (extruder, percent) (M221)
+    SetFlowFactor(u8, u32),
+    /// Set speed of a fan (fan index, speed 0.0-1.0) (M106)
+    SetFanSpeed(u8, f32),
+    /// Babystep the z axis by given mm (M290)
+    BabyStep(f32),
+    /// Home the given axes, all axes if empty
+    Home(Vec<Axis>),
+    /// Move relative to the current position (distances, feedrate mm/min)
+    Jog(Coordinates, u32),
+    /// Move to absolute coordinates (position, feedrate mm/min)
+    MoveTo(Coordinates, u32),
+    /// Extrude given length in mm, negative values retract (length, feedrate mm/min)
+    Extrude(f32, u32),
+    /// Disable all stepper motors
+    DisableMotors,
+    /// Add a file given by path to the job queue
+    EnqueuePrint(String),
+    /// Remove a pending job from the job queue (job id)
+    RemoveJob(u32),
+    /// Move a pending job to a new position in the job queue (job id, position)
+    MoveJob(u32, usize),
+    /// Start the next job of the job queue
+    StartNextJob,
+    /// Set what happens when a print of the job queue finished
+    SetAutoStart(AutoStart),
+    /// Confirm that the bed is cleared and the next job can be started
+    ConfirmBedClear,
+    /// Request the last n entries of the print history
+    RequestHistory(usize),
+    /// Request the thumbnails embedded in a file given by path
+    RequestThumbnails(String),
+    /// Choose which status updates are sent to the printer display (M73/M117)
+    SetDisplayUpdates(DisplayUpdates),
+    /// Continue after the filament was changed on a printer without display (M108)
+    ConfirmFilamentChange,
+    /// Stop printing an object of the active print (object id)
+    CancelObject(u32),
+    /// Choose a button of the prompt shown by the printer (button index)
+    RespondToPrompt(u8),
+    /// Enable the thermal watchdog with the given checks or disable it with None
+    SetThermalWatchdog(Option<ThermalWatchdog>),
+    /// Turn off the heaters after the printer is idle for some time or disable the timeout with None
+    SetIdleTimeout(Option<IdleTimeout>),
+    /// Restart the idle timeout of the heaters
+    ExtendIdleTimeout,
+    /// Enable or disable MeatPack packing of the sent lines if the firmware supports it
+    SetMeatPack(bool),
+    /// List the files on the sd card, answered with SdFiles
+    ListSdFiles,
+    /// Start printing a file of the sd card (sd file name)
+    StartSdPrint(String),
+    PauseSdPrint,
+    ResumeSdPrint,
+    /// Abort the sd card print (M524)
+    StopSdPrint,
+    /// Request progress and file of the sd card print
+    RequestSdProgress,
+    /// Delete a file of the sd card (sd file name)
+    DeleteSdFile(String),
+    /// Upload a local file to the sd card (local path, sd file name)
+    UploadToSd(String, String),
 }
@@ -1,4 +1,6 @@
+use crate::models::metadata::GcodeMetadata;
 use serde::{Deserialize, Serialize};
+use strum::Display;
 
 #[derive(Serialize, Clone, Deserialize)]
 pub struct GcodeFile {
@@ -6,6 +8,8 @@ pub struct GcodeFile {
     pub size: u64,
     pub last_modified: u128,
     pub start_time: u128,
+    #[serde(default)]
+    pub metadata: GcodeMetadata,
 }
 
 #[derive(Serialize, Clone, Deserialize)]
@@ -15,4 +19,34 @@ pub struct FinishedPrint {
     pub last_modified: u128,
     pub start_time: u128,
     pub finish_time: u128,
+    pub outcome: PrintOutcome,
+    /// extruded filament in mm
+    pub filament_used: f32,
+    /// number of layers printed
+    pub layer_reached: u32,
+    /// highest z position reached
+    pub max_z: f32,
+    /// seconds spent printing, without heatups and pauses
+    #[serde(default)]
+    pub print_time: f32,
+}
+
+/// How a print ended
+#[derive(Serialize, Clone, Copy, Deserialize, PartialEq, Eq, Display, Debug)]
+pub enum PrintOutcome {
+    Completed,
+    Cancelled,
+    Errored,
+    /// the host stopped while the print was active
+    PowerLoss,
+}
+
+/// Statistics of the active print collected while sending
+#[derive(Serialize, Clone, Default, Deserialize)]
+pub(crate) struct JobStats {
+    pub filament_used: f32,
+    pub layer_reached: u32,
+    pub max_z: f32,
+    /// seconds spent sending lines while printing, without heatups and pauses
+    pub print_time: f32,
 }
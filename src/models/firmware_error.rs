@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+/// Error reported by the firmware
+#[derive(Serialize, Clone, Deserialize, PartialEq, Debug)]
+pub struct FirmwareError {
+    pub kind: FirmwareErrorKind,
+    /// line the firmware sent
+    pub message: String,
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize, PartialEq, Eq, Display, Debug)]
+pub enum FirmwareErrorKind {
+    /// the line number of the sent line was not the expected one, the line is sent again
+    LineNumber,
+    /// the sent line was corrupted, the line is sent again
+    Checksum,
+    /// the command is not supported by the firmware and skipped
+    UnknownCommand,
+    /// the firmware stopped and needs a reset
+    Halted,
+    ThermalRunaway,
+    MinTemp,
+    MaxTemp,
+    HeatingFailed,
+    /// the printer did not answer
+    NoResponse,
+    Other,
+}
+
+impl FirmwareError {
+    pub fn no_response(message: &str) -> FirmwareError {
+        FirmwareError {
+            kind: FirmwareErrorKind::NoResponse,
+            message: message.to_string(),
+        }
+    }
+
+    /// the print can not continue after this error
+    pub fn is_fatal(&self) -> bool {
+        !matches!(
+            self.kind,
+            FirmwareErrorKind::LineNumber
+                | FirmwareErrorKind::Checksum
+                | FirmwareErrorKind::UnknownCommand
+        )
+    }
+}
@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+/// A print job waiting in the job queue
+#[derive(Serialize, Clone, Deserialize)]
+pub struct QueuedJob {
+    pub id: u32,
+    pub name: String,
+    pub size: u64,
+    pub last_modified: u128,
+    pub added_time: u128,
+}
+
+/// Object of the active print which can be cancelled on its own
+#[derive(Serialize, Clone, Deserialize, Debug)]
+pub struct PrintObject {
+    pub id: u32,
+    pub name: String,
+    /// area of the extruded moves of the object
+    pub bounds: Option<BoundingBox>,
+    pub cancelled: bool,
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize, Debug)]
+pub struct BoundingBox {
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32,
+}
+
+/// Progress of the active print
+#[derive(Serialize, Clone, Deserialize, Debug, Default)]
+pub struct JobProgress {
+    pub bytes_sent: u64,
+    pub bytes_total: u64,
+    /// gcode commands of the file, without comments and empty lines
+    pub lines_sent: u32,
+    pub lines_total: u32,
+    /// share of the estimated print time which is done (0.0 - 1.0)
+    pub time_fraction: f32,
+    /// time based if an estimate exists, byte based otherwise (0.0 - 100.0)
+    pub percent: f32,
+}
+
+/// Behaviour of the job queue when a print finished
+#[derive(Serialize, Clone, Copy, Deserialize, PartialEq, Eq)]
+pub enum AutoStart {
+    /// next job is only started by a StartNextJob command
+    Off,
+    /// next job is started as soon as the previous print finished
+    Immediate,
+    /// next job is started after the bed clear is confirmed
+    AfterBedClear,
+}
+
+/// Status updates sent to the printer display while printing
+#[derive(Serialize, Clone, Copy, Deserialize, Default, Debug)]
+pub struct DisplayUpdates {
+    /// progress and remaining time with M73
+    pub progress: bool,
+    /// job name, layer and pause messages with M117
+    pub messages: bool,
+}
+
+/// Lifecycle state of the active print job
+#[derive(Serialize, Clone, Deserialize, PartialEq, Debug, Display)]
+pub enum JobState {
+    /// file is loaded, no line is sent yet
+    Queued,
+    /// waiting for the heaters to reach their target
+    Heating,
+    Printing,
+    /// pause requested, waiting for the running moves to finish
+    Pausing,
+    Paused,
+    /// cancel requested, the ending gcode is sent
+    Cancelling,
+    Cancelled,
+    Completed,
+    Failed {
+        reason: String,
+    },
+}
+
+impl JobState {
+    /// the job ended and no further transitions except a new job are possible
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            JobState::Cancelled | JobState::Completed | JobState::Failed { .. }
+        )
+    }
+
+    /// check if the transition to the next state is valid
+    pub fn can_transition_to(&self, next: &JobState) -> bool {
+        use JobState::*;
+        match (self, next) {
+            (_, Failed { .. }) => !self.is_terminal(),
+            (Queued, Heating | Printing | Cancelling) => true,
+            (Heating, Printing | Pausing | Cancelling) => true,
+            (Printing, Heating | Pausing | Cancelling | Completed) => true,
+            (Pausing, Printing | Paused | Cancelling) => true,
+            (Paused, Printing | Cancelling) => true,
+            (Cancelling, Cancelled) => true,
+            (Cancelled | Completed | Failed { .. }, Queued) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Action run when a print reaches a layer
+#[derive(Serialize, Clone, Deserialize, Debug)]
+pub enum LayerAction {
+    /// pause the print before the layer starts
+    Pause,
+    /// change the filament with M600 before the layer starts
+    FilamentChange,
+    /// send the given gcode lines before the layer starts
+    Script(Vec<String>),
+}
+
+/// When a layer hook runs
+#[derive(Serialize, Clone, Deserialize, Debug)]
+pub enum HookTrigger {
+    /// before the layer with the given 0 based index starts
+    Layer(u32),
+    /// before the first layer at or above the given z height starts
+    Height(f32),
+}
+
+/// Runs an action once when the active print reaches the trigger of the hook
+#[derive(Serialize, Clone, Deserialize, Debug)]
+pub struct LayerHook {
+    pub trigger: HookTrigger,
+    pub action: LayerAction,
+}
+
+impl LayerHook {
+    /// check if the hook runs before the given layer
+    pub(crate) fn is_triggered(&self, layer: u32, z: f32) -> bool {
+        match self.trigger {
+            HookTrigger::Layer(l) => layer >= l,
+            // layer heights are rounded by the slicer
+            HookTrigger::Height(h) => z >= h - 0.001,
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+/// Slicer which generated a gcode file
+#[derive(Serialize, Clone, Copy, Deserialize, PartialEq, Eq, Display, Debug)]
+pub enum Slicer {
+    PrusaSlicer,
+    SuperSlicer,
+    OrcaSlicer,
+    Cura,
+    Simplify3D,
+    IdeaMaker,
+}
+
+/// Metadata of a gcode file read from the slicer comments
+#[derive(Serialize, Clone, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct GcodeMetadata {
+    pub slicer: Option<Slicer>,
+    pub slicer_version: Option<String>,
+    /// estimated print time in seconds
+    pub estimated_time: Option<u32>,
+    /// filament length in mm per extruder
+    pub filament_length: Vec<f32>,
+    /// filament weight in g per extruder
+    pub filament_weight: Vec<f32>,
+    pub layer_height: Option<f32>,
+    pub nozzle_diameter: Option<f32>,
+    pub bed_temp: Option<f32>,
+    pub nozzle_temp: Option<f32>,
+    /// highest z position of the print
+    pub max_layer_z: Option<f32>,
+    pub object_names: Vec<String>,
+    /// thumbnails embedded in the file, the image data can be read with `extract_thumbnails`
+    pub thumbnails: Vec<ThumbnailInfo>,
+}
+
+/// Image format of an embedded thumbnail
+#[derive(Serialize, Clone, Copy, Deserialize, PartialEq, Eq, Display, Debug)]
+pub enum ThumbnailFormat {
+    Png,
+    Qoi,
+    Jpg,
+}
+
+/// Size and format of a thumbnail embedded in a gcode file
+#[derive(Serialize, Clone, Deserialize, PartialEq, Eq, Debug)]
+pub struct ThumbnailInfo {
+    pub width: u32,
+    pub height: u32,
+    pub format: ThumbnailFormat,
+}
+
+/// Thumbnail embedded in a gcode file
+#[derive(Serialize, Clone, Deserialize, Debug)]
+pub struct Thumbnail {
+    pub info: ThumbnailInfo,
+    /// base64 encoded image data
+    pub data: String,
+}
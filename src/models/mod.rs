@@ -1,4 +1,12 @@
 pub mod action;
 pub mod file;
+pub mod firmware_error;
+pub mod job;
+pub mod metadata;
+pub mod motion;
+pub mod policy;
+pub mod profile;
+pub mod sd_card;
 pub mod serial_connector;
 pub mod temperature;
+pub mod validation;
@@ -0,0 +1,100 @@
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+/// Axis of the printer
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Display, Debug)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+/// Coordinates of a move, axes set to None are not moved
+#[derive(Clone, Serialize, Deserialize, Default, Debug)]
+pub struct Coordinates {
+    pub x: Option<f32>,
+    pub y: Option<f32>,
+    pub z: Option<f32>,
+}
+
+impl Coordinates {
+    /// format coordinates as gcode parameters eg. "X10 Z0.2", fails if no axis is set
+    pub(crate) fn to_params(&self) -> anyhow::Result<String> {
+        let params = [(Axis::X, self.x), (Axis::Y, self.y), (Axis::Z, self.z)]
+            .iter()
+            .filter_map(|(a, v)| v.map(|v| format!("{}{:.3}", a, v)))
+            .collect::<Vec<String>>();
+        if params.is_empty() {
+            bail!("no axis to move");
+        }
+        Ok(params.join(" "))
+    }
+}
+
+/// positioning modes of the printer as last sent to it
+#[derive(Clone, Copy, Default)]
+pub(crate) struct MotionModes {
+    /// G91 active
+    pub relative_moves: bool,
+    /// M83 active
+    pub relative_extrusion: bool,
+    /// G90 or G91 was sent since the printer started
+    pub moves_known: bool,
+    /// the extrusion mode was sent since the printer started
+    pub extrusion_known: bool,
+}
+
+impl MotionModes {
+    /// gcodes to restore these modes, modes which were never sent are left to the firmware
+    pub(crate) fn restore_cmds(&self) -> Vec<String> {
+        let mut cmds = vec![];
+        if self.moves_known {
+            cmds.push(if self.relative_moves { "G91" } else { "G90" }.to_string());
+        }
+        if self.extrusion_known {
+            cmds.push(
+                if self.relative_extrusion {
+                    "M83"
+                } else {
+                    "M82"
+                }
+                .to_string(),
+            );
+        }
+        cmds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coordinates_without_axis_are_rejected() {
+        assert!(Coordinates::default().to_params().is_err());
+        let c = Coordinates {
+            x: Some(10.0),
+            z: Some(0.2),
+            ..Default::default()
+        };
+        assert_eq!(c.to_params().unwrap(), "X10.000 Z0.200");
+    }
+
+    #[test]
+    fn only_known_modes_are_restored() {
+        assert!(MotionModes::default().restore_cmds().is_empty());
+        let modes = MotionModes {
+            relative_extrusion: true,
+            extrusion_known: true,
+            ..Default::default()
+        };
+        assert_eq!(modes.restore_cmds(), vec!["M83"]);
+        let modes = MotionModes {
+            moves_known: true,
+            extrusion_known: true,
+            ..Default::default()
+        };
+        assert_eq!(modes.restore_cmds(), vec!["G90", "M82"]);
+    }
+}
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+/// Restrictions for the commands of users and the lines of printed files
+#[derive(Serialize, Clone, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct CommandPolicy {
+    /// only these commands are sent if not empty (e.g. "G28", "M104")
+    pub allow: Vec<String>,
+    /// commands which are never sent (e.g. "M500", "M502", "M997")
+    pub deny: Vec<String>,
+    /// highest extruder temperature a command may set in °C
+    pub max_extruder_temp: Option<f32>,
+    /// highest bed temperature a command may set in °C
+    pub max_bed_temp: Option<f32>,
+    /// reject manual commands while a print job is active, the job itself can still be paused and controlled
+    pub block_manual_during_job: bool,
+}
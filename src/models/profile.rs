@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+/// Description of the connected printer
+#[derive(Serialize, Clone, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct PrinterProfile {
+    pub motion: MotionLimits,
+    pub heaters: HeaterLimits,
+    /// moves of a file are not checked without build volume
+    pub build_volume: Option<BuildVolume>,
+    /// tools of a file are not checked without the number of extruders
+    pub extruders: Option<u8>,
+}
+
+/// Positions the nozzle can reach in mm, axes are ordered X, Y, Z
+#[derive(Serialize, Clone, Copy, Deserialize, Debug)]
+pub struct BuildVolume {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+/// Temperature limits of the heaters in °C, temperatures are not checked without limit
+#[derive(Serialize, Clone, Copy, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct HeaterLimits {
+    pub max_extruder_temp: Option<f32>,
+    pub max_bed_temp: Option<f32>,
+}
+
+/// Motion limits of the printer, axes are ordered X, Y, Z, E
+#[derive(Serialize, Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct MotionLimits {
+    /// max feedrate per axis in mm/s (M203)
+    pub max_feedrate: [f32; 4],
+    /// max acceleration per axis in mm/s² (M201)
+    pub max_acceleration: [f32; 4],
+    /// acceleration of printing moves in mm/s² (M204 P)
+    pub print_acceleration: f32,
+    /// acceleration of extruder only moves in mm/s² (M204 R)
+    pub retract_acceleration: f32,
+    /// acceleration of travel moves in mm/s² (M204 T)
+    pub travel_acceleration: f32,
+    /// max instant speed change per axis in mm/s, used without junction deviation (M205 X Y Z E)
+    pub jerk: [f32; 4],
+    /// junction deviation in mm (M205 J)
+    pub junction_deviation: Option<f32>,
+}
+
+impl Default for MotionLimits {
+    // marlin defaults
+    fn default() -> Self {
+        MotionLimits {
+            max_feedrate: [300.0, 300.0, 5.0, 25.0],
+            max_acceleration: [3000.0, 3000.0, 100.0, 10000.0],
+            print_acceleration: 3000.0,
+            retract_acceleration: 3000.0,
+            travel_acceleration: 3000.0,
+            jerk: [10.0, 10.0, 0.3, 5.0],
+            junction_deviation: Some(0.013),
+        }
+    }
+}
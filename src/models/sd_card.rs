@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+/// A file on the sd card of the printer
+#[derive(Serialize, Clone, Deserialize, Debug)]
+pub struct SdFile {
+    /// dos 8.3 name used by the firmware commands
+    pub name: String,
+    /// long file name if the firmware reports it
+    pub long_name: Option<String>,
+    pub size: u64,
+}
+
+/// Progress of a file upload to the sd card
+#[derive(Serialize, Clone, Deserialize, Debug)]
+pub struct SdUploadProgress {
+    pub name: String,
+    pub bytes_sent: u64,
+    pub bytes_total: u64,
+}
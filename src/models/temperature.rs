@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
+use strum::Display as StrumDisplay;
 
 #[derive(Serialize, Debug, Clone, Deserialize)]
 pub struct Temperature {
@@ -23,3 +24,83 @@ impl Default for &Temperature {
         }
     }
 }
+
+#[derive(Serialize, Clone, Copy, Deserialize, PartialEq, Eq, StrumDisplay, Debug)]
+pub enum Heater {
+    Extruder,
+    Bed,
+}
+
+/// Host side checks of the reported temperatures
+#[derive(Serialize, Clone, Copy, Deserialize, Debug)]
+#[serde(default)]
+pub struct ThermalWatchdog {
+    /// seconds a heater may take to reach its target
+    pub heatup_timeout: u32,
+    /// °C the temperature may drop below the highest reading while heating and below the target after it was reached
+    pub max_drop: f32,
+    pub reaction: ThermalReaction,
+}
+
+impl Default for ThermalWatchdog {
+    fn default() -> Self {
+        ThermalWatchdog {
+            heatup_timeout: 600,
+            max_drop: 5.0,
+            reaction: ThermalReaction::HeatersOff,
+        }
+    }
+}
+
+/// What the watchdog does when a check fails, the active print fails in both cases
+#[derive(Serialize, Clone, Copy, Deserialize, PartialEq, Eq, Default, Debug)]
+pub enum ThermalReaction {
+    /// turn off all heaters (M104 S0, M140 S0)
+    #[default]
+    HeatersOff,
+    /// stop the printer immediately (M112), the printer needs a reset
+    EmergencyStop,
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize, PartialEq, Eq, StrumDisplay, Debug)]
+pub enum ThermalAlarmKind {
+    /// the heater did not reach its target within the heatup timeout
+    HeatupTimeout,
+    /// the temperature dropped while heating
+    TemperatureDrop,
+    /// the temperature is above the limit of the printer profile
+    OutOfRange,
+    /// the heater reports 0 or negative temperatures
+    SensorDisconnected,
+}
+
+/// A check of the thermal watchdog failed
+#[derive(Serialize, Clone, Deserialize, Debug)]
+pub struct ThermalAlarm {
+    pub heater: Heater,
+    pub kind: ThermalAlarmKind,
+    pub temp: f32,
+    pub target: f32,
+}
+
+/// Heaters are turned off after the printer was idle with heaters on for the given time
+#[derive(Serialize, Clone, Copy, Deserialize, Debug)]
+#[serde(default)]
+pub struct IdleTimeout {
+    /// seconds the printer may be idle with heaters on
+    pub timeout: u32,
+    /// seconds before the timeout the warning is sent
+    pub warning: u32,
+    /// also disable the stepper motors (M84)
+    pub disable_motors: bool,
+}
+
+impl Default for IdleTimeout {
+    fn default() -> Self {
+        IdleTimeout {
+            timeout: 1800,
+            warning: 60,
+            disable_motors: false,
+        }
+    }
+}
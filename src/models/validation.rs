@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+/// Result of checking a gcode file against the printer profile
+#[derive(Serialize, Clone, Deserialize, Default, Debug)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// the file must not be printed unless the start is forced
+    pub fn has_errors(&self) -> bool {
+        self.issues
+            .iter()
+            .any(|i| i.severity != IssueSeverity::Warning)
+    }
+
+    /// the file must not be printed even if the start is forced
+    pub fn is_denied(&self) -> bool {
+        self.issues
+            .iter()
+            .any(|i| i.severity == IssueSeverity::Denied)
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize, PartialEq, Eq, Display, Debug)]
+pub enum IssueSeverity {
+    Warning,
+    Error,
+    /// the command policy denies the line
+    Denied,
+}
+
+/// A problem found in the file, lines with the same problem are counted
+#[derive(Serialize, Clone, Deserialize, Debug)]
+pub struct ValidationIssue {
+    pub severity: IssueSeverity,
+    /// line of the file where the issue was found first (1 based)
+    pub line: u32,
+    /// number of lines with this issue
+    pub count: u32,
+    pub message: String,
+}
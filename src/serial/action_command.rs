@@ -0,0 +1,72 @@
+use crate::models::action::{Action, HostPrompt, PrinterAction};
+use crate::serial::event_loop::Serial;
+use log::{info, warn};
+
+impl Serial {
+    /// handle a `//action:<name> <args>` line of the firmware
+    pub(crate) fn handle_action_command(&mut self, name: &str, args: &str) {
+        let action = match name {
+            "cancel" => {
+                warn!("canceling print job");
+                PrinterAction::Cancel
+            }
+            "pause" => {
+                warn!("Pause Command from Printer received");
+                PrinterAction::Pause
+            }
+            "resume" => {
+                warn!("Continue Command from Printer received");
+                PrinterAction::Resume
+            }
+            "paused" => PrinterAction::Paused,
+            "resumed" => PrinterAction::Resumed,
+            // out_of_filament T0
+            "out_of_filament" => {
+                let extruder = args.strip_prefix('T').and_then(|t| t.trim().parse().ok());
+                self.start_filament_change(extruder, true);
+                PrinterAction::OutOfFilament(extruder)
+            }
+            "notification" => PrinterAction::Notification(args.to_string()),
+            "probe_failed" => PrinterAction::ProbeFailed,
+            "start" => PrinterAction::Start,
+
+            // prompt_begin <message>, prompt_button <text>..., prompt_show, prompt_end
+            "prompt_begin" => {
+                self.prompt = Some(HostPrompt {
+                    message: args.to_string(),
+                    buttons: vec![],
+                });
+                return;
+            }
+            "prompt_button" | "prompt_choice" => {
+                match self.prompt.as_mut() {
+                    Some(p) => p.buttons.push(args.to_string()),
+                    None => warn!("Prompt button without prompt: {}", args),
+                }
+                return;
+            }
+            "prompt_show" => match self.prompt.clone() {
+                Some(p) => {
+                    *self.job.prompt.lock().unwrap() = Some(p.clone());
+                    PrinterAction::Prompt(p)
+                }
+                None => {
+                    warn!("Prompt shown without prompt_begin");
+                    return;
+                }
+            },
+            "prompt_end" => {
+                self.prompt = None;
+                *self.job.prompt.lock().unwrap() = None;
+                PrinterAction::PromptEnd
+            }
+            v => {
+                warn!("Unknown action Command received: {}", v);
+                return;
+            }
+        };
+
+        info!("Printer action: {:?}", action);
+        let _ = self.tx.send(Action::PrinterAction(action));
+    }
+}
@@ -0,0 +1,74 @@
+use crate::models::action::{PrinterStatus, TelemetryData};
+use crate::models::job::JobState;
+use crate::models::motion::MotionModes;
+use crate::serial::event_loop::Serial;
+use crate::serial::host_command::HOST_CMD_MEATPACK;
+use lazy_static::lazy_static;
+use log::warn;
+use regex::Regex;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    // start / echo:Marlin 2.1.2 / echo: External Reset / echo: Brown out Reset / echo: Watchdog Reset
+    static ref RE_BOOT_BANNER: Regex =
+        Regex::new(r"^(start$|echo:\s*Marlin\s|echo:\s*(External Reset|Brown out Reset|Watchdog Reset|Software Reset|Power-Up)$)").unwrap();
+}
+
+/// lines of one boot banner are not counted as further restarts
+const BANNER_DURATION: Duration = Duration::from_secs(10);
+
+/// commands sent after connecting and after a restart of the printer,
+/// the line numbering is reset and the capability report tells if the firmware supports host prompts
+pub(crate) const HANDSHAKE: [&str; 2] = ["M110 N0", "M115"];
+
+impl Serial {
+    pub(crate) fn handle_boot_response(&mut self, line: &str) {
+        if !RE_BOOT_BANNER.is_match(line) {
+            return;
+        }
+        if self
+            .last_boot
+            .is_some_and(|t| t.elapsed() < BANNER_DURATION)
+        {
+            return;
+        }
+        self.last_boot = Some(Instant::now());
+        self.handle_printer_restart();
+    }
+
+    /// the printer lost its state, nothing that was sent before can be continued
+    fn handle_printer_restart(&mut self) {
+        warn!("Printer restarted");
+        let reason = "Printer restarted".to_string();
+
+        self.upload_failed(&reason);
+        self.que.lock().unwrap().clear();
+        self.job.lines.lock().unwrap().clear();
+        if self.job.is_active() {
+            self.job.transition(JobState::Failed { reason });
+        }
+
+        self.resends = 0;
+        self.line_numbers.reset();
+        self.ignored_oks = 0;
+        self.skipped_moves = None;
+        self.prompt = None;
+        *self.job.prompt.lock().unwrap() = None;
+        self.sd_listing = None;
+        self.filament_change.store(false, Ordering::Relaxed);
+        self.filament_change_skip_ok = false;
+        self.job.sd_printing.store(false, Ordering::Relaxed);
+        *self.motion_modes.lock().unwrap() = MotionModes::default();
+        self.send_telemetry(TelemetryData::PrinterRestarted);
+        self.update_status(PrinterStatus::Idle);
+
+        let mut que = self.que.lock().unwrap();
+        que.extend(HANDSHAKE.iter().map(|c| c.to_string()));
+        // the firmware starts unpacked, packing is negotiated again
+        if self.meatpack.active {
+            self.meatpack.active = false;
+            que.push_back(format!("{} on", HOST_CMD_MEATPACK));
+        }
+    }
+}
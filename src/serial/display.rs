@@ -0,0 +1,69 @@
+use crate::models::job::DisplayUpdates;
+use crate::serial::event_loop::Serial;
+use std::time::{Duration, Instant};
+
+/// minimum time between two display updates of the same kind
+const DISPLAY_INTERVAL: Duration = Duration::from_secs(10);
+/// longer messages are cut, most printer displays show 20 characters per line
+const MAX_MESSAGE_LEN: usize = 40;
+
+/// M117 command showing a message on the printer display
+pub(crate) fn display_message_cmd(msg: &str) -> String {
+    // a ; would start a comment and cut the message
+    let msg: String = msg
+        .chars()
+        .filter(|c| c.is_ascii() && !c.is_ascii_control() && *c != ';')
+        .take(MAX_MESSAGE_LEN)
+        .collect();
+    format!("M117 {}", msg.trim())
+}
+
+impl Serial {
+    fn display_updates(&self) -> DisplayUpdates {
+        *self.job.display.lock().unwrap()
+    }
+
+    /// show the progress of the print with M73, `remaining` in seconds
+    pub(crate) fn update_display_progress(&mut self, percent: f32, remaining: Option<u32>) {
+        if !self.display_updates().progress {
+            return;
+        }
+
+        let percent = percent as u32;
+        let minutes = remaining.map(|r| r.div_ceil(60));
+        let due = match self.last_display_progress {
+            None => true,
+            // the end of the print is always shown
+            Some((t, p, m)) => {
+                (p, m) != (percent, minutes) && (percent >= 100 || t.elapsed() >= DISPLAY_INTERVAL)
+            }
+        };
+        if !due {
+            return;
+        }
+
+        self.last_display_progress = Some((Instant::now(), percent, minutes));
+        let cmd = match minutes {
+            Some(m) => format!("M73 P{} R{}", percent, m),
+            None => format!("M73 P{}", percent),
+        };
+        self.que.lock().unwrap().push_back(cmd);
+    }
+
+    /// show a status message with M117, rate limited unless forced
+    pub(crate) fn display_message(&mut self, msg: &str, force: bool) {
+        if !self.display_updates().messages {
+            return;
+        }
+        if !force
+            && self
+                .last_display_message
+                .is_some_and(|t| t.elapsed() < DISPLAY_INTERVAL)
+        {
+            return;
+        }
+
+        self.last_display_message = Some(Instant::now());
+        self.que.lock().unwrap().push_back(display_message_cmd(msg));
+    }
+}
@@ -1,29 +1,101 @@
-use crate::models::action::{Action, PrinterStatus, TelemetryData};
+use crate::models::action::{Action, HostPrompt, PrinterStatus, TelemetryData};
+use crate::models::firmware_error::{FirmwareError, FirmwareErrorKind};
+use crate::models::job::JobState;
+use crate::models::motion::MotionModes;
+use crate::models::sd_card::SdFile;
 use crate::models::serial_connector::SerialConnector;
+use crate::serial::firmware_error::classify_error;
+use crate::serial::host_command::HOST_CMD_PREFIX;
+use crate::serial::idle_timeout::IdleHeaters;
+use crate::serial::job::Job;
+use crate::serial::line_numbers::LineNumbers;
+use crate::serial::meatpack::MeatPack;
+use crate::serial::objects::{LineFilter, SkippedMoves};
+use crate::serial::response::{classify_response, ResponseLine};
+use crate::serial::sd_card::SdUpload;
+use crate::serial::thermal::HeaterWatch;
 
 use event_listener::Event;
+use lazy_static::lazy_static;
 use log::{debug, error, info, warn};
+use regex::Regex;
 use serialport::{ClearBuffer, SerialPort};
 use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use std::time::Instant;
 use tokio::sync::broadcast::Sender;
 
+lazy_static! {
+    // regex to match heatups blocking until the target temperature is reached
+    static ref RE_BLOCKING_HEATUP: Regex = Regex::new(r"^M1(09|90)\b").unwrap();
+    // commands the firmware handles as soon as they are received, even while it is blocked
+    static ref RE_EMERGENCY_CMD: Regex = Regex::new(r"^M(108|112|410|876)\b").unwrap();
+}
+
+/// a line is sent again at most this often before the error counts as fatal
+const MAX_RESENDS: u32 = 3;
+
+/// State shared between GcodeSerial and the serial event loop
+#[derive(Clone)]
+pub(crate) struct SharedState {
+    pub(crate) que: Arc<Mutex<VecDeque<String>>>,
+    pub(crate) event: Arc<Mutex<Event>>,
+    pub(crate) motion_modes: Arc<Mutex<MotionModes>>,
+    pub(crate) printer_status: Arc<Mutex<PrinterStatus>>,
+    pub(crate) job: Job,
+    pub(crate) sd_upload: Arc<Mutex<Option<SdUpload>>>,
+}
+
 pub struct Serial {
     port: Box<dyn SerialPort>,
     pub(crate) que: Arc<Mutex<VecDeque<String>>>,
     pub(crate) event: Arc<Mutex<Event>>,
     pub(crate) tx: Sender<Action>,
     pub(crate) printer_status: Arc<Mutex<PrinterStatus>>,
+    pub(crate) speed_factor: u32,
+    pub(crate) flow_factors: Vec<u32>,
+    pub(crate) babystep_z: f32,
+    pub(crate) motion_modes: Arc<Mutex<MotionModes>>,
+    pub(crate) job: Job,
+    pub(crate) e_position: f32,
+    pub(crate) current_z: f32,
+    pub(crate) print_tick: Option<Instant>,
+    pub(crate) last_progress: Option<Instant>,
+    pub(crate) meatpack: MeatPack,
+    /// the printer waits for a filament change, shared with the temp interval
+    pub(crate) filament_change: Arc<AtomicBool>,
+    /// the ok of the command in flight when the firmware started the filament change does not end it
+    pub(crate) filament_change_skip_ok: bool,
+    /// line was sent again after a recoverable error since the last ok
+    pub(crate) resends: u32,
+    /// line numbers and checksums of the sent lines
+    pub(crate) line_numbers: LineNumbers,
+    /// oks of emergency commands which are received after the ok of the blocking command
+    pub(crate) ignored_oks: u32,
+    /// moves of cancelled objects which were not sent
+    pub(crate) skipped_moves: Option<SkippedMoves>,
+    /// dialog of the printer which is built by prompt action commands
+    pub(crate) prompt: Option<HostPrompt>,
+    /// time, percent and minutes of the last M73
+    pub(crate) last_display_progress: Option<(Instant, u32, Option<u32>)>,
+    pub(crate) last_display_message: Option<Instant>,
+    pub(crate) sd_upload: Arc<Mutex<Option<SdUpload>>>,
+    /// files received so far while the printer lists the sd card
+    pub(crate) sd_listing: Option<Vec<SdFile>>,
+    /// heaters watched by the thermal watchdog, indexed by Heater
+    pub(crate) heater_watch: [HeaterWatch; 2],
+    pub(crate) idle_heaters: IdleHeaters,
+    /// time the last boot banner was received
+    pub(crate) last_boot: Option<Instant>,
 }
 
 impl Serial {
     pub async fn new(
         tx: Sender<Action>,
         serial_connector: SerialConnector,
-        qq: Arc<Mutex<VecDeque<String>>>,
-        event: Arc<Mutex<Event>>,
+        shared: SharedState,
     ) -> Self {
         let (name, boud) = match serial_connector {
             SerialConnector::Auto => {
@@ -43,7 +115,7 @@ impl Serial {
                 };
 
                 let pname = &ports[0].port_name;
-                let name = pname.split('/').last().unwrap();
+                let name = pname.split('/').next_back().unwrap();
 
                 (format!("/dev/{}", name), 115_200)
             }
@@ -72,12 +144,54 @@ impl Serial {
         p.set_timeout(Duration::from_millis(100))
             .expect("failed to set printer timeout");
 
+        Self::from_port(p, tx, shared)
+    }
+
+    /// serial state for a port which is already opened and initialized
+    pub(crate) fn from_port(
+        port: Box<dyn SerialPort>,
+        tx: Sender<Action>,
+        shared: SharedState,
+    ) -> Self {
+        let SharedState {
+            que,
+            event,
+            motion_modes,
+            printer_status,
+            job,
+            sd_upload,
+        } = shared;
         Serial {
-            port: p,
-            que: qq,
+            port,
+            que,
             event,
             tx,
-            printer_status: Arc::new(Mutex::new(PrinterStatus::Disconnected)),
+            printer_status,
+            speed_factor: 100,
+            flow_factors: vec![],
+            babystep_z: 0.0,
+            motion_modes,
+            job,
+            e_position: 0.0,
+            current_z: 0.0,
+            print_tick: None,
+            last_progress: None,
+            meatpack: MeatPack::default(),
+            prompt: None,
+            skipped_moves: None,
+            filament_change: Arc::new(AtomicBool::new(false)),
+            filament_change_skip_ok: false,
+            ignored_oks: 0,
+            resends: 0,
+            line_numbers: LineNumbers::default(),
+            last_display_progress: None,
+            last_display_message: None,
+            sd_upload,
+            sd_listing: None,
+            heater_watch: Default::default(),
+            idle_heaters: IdleHeaters::default(),
+            // opening the port resets most boards, this banner is no restart during a print
+            last_boot: Some(Instant::now()),
         }
     }
 
@@ -94,81 +208,212 @@ impl Serial {
 
     pub async fn start_event_loop(&mut self) {
         loop {
-            if self.que.lock().unwrap().is_empty() {
-                self.update_status(PrinterStatus::Idle);
+            if self.que.lock().unwrap().is_empty()
+                && !self.job_lines_pending()
+                && self.sd_upload.lock().unwrap().is_none()
+            {
+                self.handle_que_drained();
 
                 let listener = { self.event.lock().unwrap().listen() };
                 listener.await;
             }
 
-            let elem = self.que.lock().unwrap().pop_front();
-            let que_len = self.que.lock().unwrap().len() as u32;
-
-            if que_len != 0 {
-                debug!("queue size: {}", que_len);
+            // an upload to the sd card must not be interrupted by other commands,
+            // commands are always sent before the lines of the print job
+            let mut job_line = false;
+            let mut elem = self.next_upload_line();
+            let upload_line = elem.is_some();
+            if elem.is_none() {
+                elem = self.que.lock().unwrap().pop_front();
             }
-
-            // do not send telemetry when we only update temp
-            if que_len != 0 && elem != Some("M105".to_string()) {
-                self.send_telemetry(TelemetryData::Progress(que_len));
+            if elem.is_none() && self.job.is_sending() {
+                elem = self.job.lines.lock().unwrap().pop_front();
+                job_line = elem.is_some();
             }
 
             match elem {
                 None => {}
                 Some(cmd) => {
-                    // the received cmd here is expected to include no gcode comments (";") and have spaces trimmed
-                    self.handle_presend_cmd(cmd.as_str());
+                    if job_line {
+                        match self.filter_job_line(cmd.as_str()) {
+                            LineFilter::Send => {}
+                            LineFilter::Skip => continue,
+                            LineFilter::SendAfter(cmds) => {
+                                self.job.lines.lock().unwrap().push_front(cmd);
+                                let mut que = self.que.lock().unwrap();
+                                for c in cmds.into_iter().rev() {
+                                    que.push_front(c);
+                                }
+                                continue;
+                            }
+                        }
+
+                        self.update_job_state_presend(cmd.as_str());
+
+                        self.track_progress();
+                    }
 
-                    let mut buffer: Vec<u8> = cmd.as_bytes().to_vec();
-                    buffer.push(b'\n'); // Add newline character at the end of each command
-                    if let Err(e) = self.port.write_all(&buffer) {
-                        error!("Error while writing command: {}", e.to_string());
+                    if cmd.starts_with(HOST_CMD_PREFIX) {
+                        self.handle_host_cmd(cmd.as_str()).await;
                         continue;
                     }
-                    if let Err(e) = self.port.flush() {
-                        error!("Error while writing command: {}", e.to_string());
+
+                    // the received cmd here is expected to include no gcode comments (";") and have spaces trimmed
+                    // lines written to the sd card are not executed by the printer
+                    if !upload_line {
+                        self.handle_presend_cmd(cmd.as_str());
+                    }
+
+                    // lines written to the sd card are stored as they are sent
+                    let line = match upload_line {
+                        true => cmd.clone(),
+                        false => self.line_numbers.number_line(&cmd),
+                    };
+                    let buffer = self.encode_cmd(line.as_str());
+                    if let Err(e) = self.write_raw(&buffer) {
+                        error!("Error while writing command: {}", e);
                         continue;
                     }
 
-                    if cmd != "M105" {
+                    if cmd != "M105" && !upload_line {
                         info!(">>>{}", cmd);
                     }
 
                     // handle an error message
-                    if let Err(e) = self.read_until_ok().await {
-                        error!("weve received an error response!");
-                        error!("{}", e);
-
-                        // when an error occurs clear queue
-                        self.que.lock().unwrap().clear();
+                    match self.read_until_ok().await {
+                        Ok(_) => {
+                            self.resends = 0;
+                            self.finish_filament_change();
+                            if upload_line {
+                                self.upload_line_done();
+                            }
+                            // blocking heatup finished
+                            if job_line && self.job.state() == Some(JobState::Heating) {
+                                self.job.transition(JobState::Printing);
+                            }
+                        }
+                        Err(e) if !e.is_fatal() && self.resends < MAX_RESENDS => {
+                            warn!("Recoverable firmware error: {}", e.message);
+                            self.send_telemetry(TelemetryData::FirmwareError(e.clone()));
+                            match e.kind {
+                                FirmwareErrorKind::LineNumber | FirmwareErrorKind::Checksum => {
+                                    self.resends += 1;
+                                    // upload lines are only removed once they are acknowledged
+                                    if !upload_line {
+                                        self.resend_lines(cmd, job_line);
+                                    }
+                                }
+                                _ => {
+                                    if upload_line {
+                                        self.upload_line_done();
+                                    }
+                                }
+                            }
+                        }
+                        Err(e) => self.handle_fatal_error(e),
                     }
                 }
             }
         }
     }
 
-    pub async fn read_until_ok(&mut self) -> Result<Vec<String>, String> {
+    fn handle_fatal_error(&mut self, e: FirmwareError) {
+        self.resends = 0;
+        // when an error occurs clear queue
+        self.que.lock().unwrap().clear();
+        self.job.lines.lock().unwrap().clear();
+
+        // an idle printer which does not answer is disconnected, the temperature polls reconnect it
+        let busy = self.job.is_active() || self.sd_upload.lock().unwrap().is_some();
+        if e.kind == FirmwareErrorKind::NoResponse && !busy {
+            warn!("Printer does not respond: {}", e.message);
+            self.update_status(PrinterStatus::Disconnected);
+            return;
+        }
+
+        error!("weve received an error response!");
+        error!("{}", e.message);
+        self.send_telemetry(TelemetryData::FirmwareError(e.clone()));
+        self.update_status(PrinterStatus::Errored);
+        self.upload_failed(&e.message);
+        if self.job.is_active() {
+            self.job.transition(JobState::Failed { reason: e.message });
+        }
+    }
+
+    fn job_lines_pending(&self) -> bool {
+        self.job.is_sending() && !self.job.lines.lock().unwrap().is_empty()
+    }
+
+    /// job state transitions before a job line is sent
+    fn update_job_state_presend(&self, cmd: &str) {
+        if self.job.state() == Some(JobState::Queued) {
+            self.job.transition(JobState::Printing);
+        }
+        if RE_BLOCKING_HEATUP.is_match(cmd) {
+            self.job.transition(JobState::Heating);
+        }
+    }
+
+    /// all commands and available job lines are sent
+    fn handle_que_drained(&mut self) {
+        self.stop_print_time();
+
+        match self.job.state() {
+            Some(JobState::Queued | JobState::Heating | JobState::Printing) => {
+                self.job.transition(JobState::Completed);
+            }
+            Some(JobState::Pausing) => {
+                self.job.transition(JobState::Paused);
+            }
+            Some(JobState::Cancelling) => {
+                self.job.transition(JobState::Cancelled);
+            }
+            _ => {}
+        }
+
+        // a paused print and a print of the sd card are still active
+        if self.job.state() != Some(JobState::Paused)
+            && !self.job.sd_printing.load(Ordering::Relaxed)
+        {
+            self.update_status(PrinterStatus::Idle);
+        }
+    }
+
+    pub async fn read_until_ok(&mut self) -> Result<Vec<String>, FirmwareError> {
         let mut msgs: Vec<String> = Vec::new();
+        let mut error: Option<FirmwareError> = None;
+        let mut ready = false;
         let mut remainder = "".to_string();
 
         let mut timestamp = Instant::now();
 
         loop {
-            while self.port.bytes_to_read().map_err(|e1| e1.to_string())? == 0 {
+            while self
+                .port
+                .bytes_to_read()
+                .map_err(|e1| FirmwareError::no_response(&e1.to_string()))?
+                == 0
+            {
+                self.send_emergency_cmds();
+
+                // the printer waits for the user as long as the filament change takes
+                if self.filament_change.load(Ordering::Relaxed) {
+                    timestamp = Instant::now();
+                }
                 if timestamp.elapsed().as_millis() > 5_000 {
                     warn!("Receive loop did not receive any message for more than 5sec!");
                     warn!("{:?}", msgs);
-                    return Err("No response received".to_string());
+                    return Err(FirmwareError::no_response("No response received"));
                 }
                 tokio::time::sleep(Duration::from_millis(5)).await;
             }
 
             timestamp = Instant::now();
-            let response = format!(
-                "{}{}",
-                remainder,
-                self.read_port().map_err(|e| { e.to_string() })?
-            );
+            let response = match self.read_port() {
+                Ok(r) => format!("{}{}", remainder, r),
+                Err(e) => return Err(FirmwareError::no_response(&e.to_string())),
+            };
             remainder = "".to_string();
 
             let mut lines: Vec<&str> = response.split('\n').collect();
@@ -184,23 +429,104 @@ impl Serial {
                 }
                 info!("<<<{}", line);
 
+                let response = classify_response(line);
+                if self.ignored_oks > 0 && matches!(response, ResponseLine::Ok(_)) {
+                    self.ignored_oks -= 1;
+                    continue;
+                }
+
                 self.handle_response(line);
                 msgs.push(line.to_string());
+
+                ready |= response.is_ok();
+
+                if let ResponseLine::Resend(Some(n)) = response {
+                    self.line_numbers.resend_from.get_or_insert(n);
+                }
+
+                // errors are only reported in error and echo lines
+                let line_error = match response {
+                    ResponseLine::Error(_) | ResponseLine::Echo(_) => classify_error(line),
+                    ResponseLine::Resend(_) => Some(FirmwareError {
+                        kind: FirmwareErrorKind::LineNumber,
+                        message: line.to_string(),
+                    }),
+                    _ => None,
+                };
+
+                // the first error is kept unless a fatal one follows
+                if let Some(e) = line_error {
+                    if error
+                        .as_ref()
+                        .is_none_or(|prev| e.is_fatal() && !prev.is_fatal())
+                    {
+                        error = Some(e);
+                    }
+                }
             }
 
+            // the printer does not answer with ok after a fatal error,
+            // after a recoverable one the ok is awaited to stay in sync
+            if let Some(e) = error.take_if(|e| e.is_fatal()) {
+                return Err(e);
+            }
             // if printer is restarted there might be no 'ok' message
-            if msgs.iter().any(|x| x.contains("ok") || x.contains("start")) {
-                return Ok(msgs);
-            } else if msgs
-                .iter()
-                .any(|x| x.contains("error") || x.contains("Error") || x.contains("Err"))
-            {
-                return Err(msgs.join(";").to_string());
+            if ready {
+                return match error {
+                    None => Ok(msgs),
+                    Some(e) => Err(e),
+                };
+            }
+        }
+    }
+
+    /// queue the lines the firmware requested again, they are numbered from the line it expects
+    fn resend_lines(&mut self, cmd: String, job_line: bool) {
+        let mut lines = self.line_numbers.take_resend();
+        if lines.last() == Some(&cmd) {
+            lines.pop();
+        }
+        // job lines go back to the job to keep progress and object filter in order
+        if job_line {
+            self.job.lines.lock().unwrap().push_front(cmd);
+        } else {
+            self.que.lock().unwrap().push_front(cmd);
+        }
+        // earlier lines the firmware discarded are sent first
+        let mut que = self.que.lock().unwrap();
+        for line in lines.into_iter().rev() {
+            que.push_front(line);
+        }
+    }
+
+    /// send emergency commands of the que right away, the printer might be blocked by the last command
+    fn send_emergency_cmds(&mut self) {
+        let cmds: Vec<String> = {
+            let mut que = self.que.lock().unwrap();
+            let (emergency, rest): (VecDeque<String>, VecDeque<String>) =
+                que.drain(..).partition(|c| RE_EMERGENCY_CMD.is_match(c));
+            *que = rest;
+            emergency.into()
+        };
+
+        for cmd in cmds {
+            info!(">>>{}", cmd);
+            let buffer = self.encode_cmd(&cmd);
+            match self.write_raw(&buffer) {
+                // the ok is sent after the one of the blocking command
+                Ok(_) => self.ignored_oks += 1,
+                Err(e) => error!("Error while writing command: {}", e),
             }
         }
     }
 
-    fn read_port(&mut self) -> anyhow::Result<String> {
+    pub(crate) fn write_raw(&mut self, buffer: &[u8]) -> anyhow::Result<()> {
+        self.port.write_all(buffer)?;
+        self.port.flush()?;
+        Ok(())
+    }
+
+    pub(crate) fn read_port(&mut self) -> anyhow::Result<String> {
         let read_buf_size = self.port.bytes_to_read()?;
         let mut serial_buf: Vec<u8> = vec![0; read_buf_size as usize];
         let num_bytes_read = self.port.read(serial_buf.as_mut_slice())?;
@@ -208,3 +534,55 @@ impl Serial {
         Ok(response)
     }
 }
+
+/// a serial connected to a pseudo terminal instead of a printer
+#[cfg(test)]
+pub(crate) fn test_serial() -> (Serial, tokio::sync::broadcast::Receiver<Action>) {
+    let (tx, rx) = tokio::sync::broadcast::channel(64);
+    let (port, _) = serialport::TTYPort::pair().expect("cannot open pty");
+    let shared = SharedState {
+        que: Arc::new(Mutex::new(VecDeque::new())),
+        event: Arc::new(Mutex::new(Event::new())),
+        motion_modes: Arc::new(Mutex::new(MotionModes::default())),
+        printer_status: Arc::new(Mutex::new(PrinterStatus::Idle)),
+        job: Job::new(tx.clone()),
+        sd_upload: Arc::new(Mutex::new(None)),
+    };
+    let serial = Serial::from_port(Box::new(port), tx, shared);
+    (serial, rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(serial: &Serial) -> PrinterStatus {
+        serial.printer_status.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn idle_printer_without_response_is_disconnected() {
+        let (mut serial, _rx) = test_serial();
+        serial.que.lock().unwrap().push_back("G28".to_string());
+        serial.handle_fatal_error(FirmwareError::no_response("No response received"));
+        assert!(status(&serial) == PrinterStatus::Disconnected);
+        assert!(serial.que.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn printing_printer_without_response_is_errored() {
+        let (mut serial, _rx) = test_serial();
+        serial.job.transition(JobState::Queued);
+        serial.job.transition(JobState::Printing);
+        serial.handle_fatal_error(FirmwareError::no_response("No response received"));
+        assert!(status(&serial) == PrinterStatus::Errored);
+        assert!(matches!(serial.job.state(), Some(JobState::Failed { .. })));
+    }
+
+    #[test]
+    fn firmware_error_of_idle_printer_is_errored() {
+        let (mut serial, _rx) = test_serial();
+        serial.handle_fatal_error(classify_error("Error:Printer halted. kill() called!").unwrap());
+        assert!(status(&serial) == PrinterStatus::Errored);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::models::action::TelemetryData;
+use crate::serial::event_loop::Serial;
+use lazy_static::lazy_static;
+use log::info;
+use regex::Regex;
+use std::sync::atomic::Ordering;
+
+lazy_static! {
+    // echo:Insert filament and send M108, echo:enqueueing "M600", echo:Filament runout
+    // "busy: paused for user" is also sent for M0/M1 and prompts, it is no filament change
+    static ref RE_FILAMENT_CHANGE: Regex =
+        Regex::new(r#"(?i)(insert filament|enqueueing "M600|filament runout)"#).unwrap();
+}
+
+impl Serial {
+    /// the printer waits for the filament to be changed, timeouts are suspended until it continues.
+    /// a change started by the firmware runs after the command in flight, its ok does not end the change
+    pub(crate) fn start_filament_change(&mut self, extruder: Option<u8>, by_firmware: bool) {
+        if self.filament_change.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        self.filament_change_skip_ok = by_firmware;
+        info!("Filament change started");
+        // the time waiting for the user is no print time
+        self.stop_print_time();
+        self.send_telemetry(TelemetryData::FilamentChangeRequired(extruder));
+    }
+
+    /// a command was acknowledged, the printer is not blocked by a filament change anymore
+    pub(crate) fn finish_filament_change(&mut self) {
+        if std::mem::take(&mut self.filament_change_skip_ok) {
+            return;
+        }
+        if self.filament_change.swap(false, Ordering::Relaxed) {
+            info!("Filament change finished");
+            self.send_telemetry(TelemetryData::FilamentChangeFinished);
+        }
+    }
+
+    pub(crate) fn handle_filament_response(&mut self, line: &str) {
+        if RE_FILAMENT_CHANGE.is_match(line) {
+            self.start_filament_change(None, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::action::{Action, TelemetryData};
+    use crate::serial::event_loop::test_serial;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn m600_starts_filament_change() {
+        for line in [
+            "echo:Insert filament and send M108",
+            r#"echo:enqueueing "M600""#,
+            "echo:Filament runout",
+        ] {
+            let (mut serial, _rx) = test_serial();
+            serial.handle_filament_response(line);
+            assert!(serial.filament_change.load(Ordering::Relaxed), "{}", line);
+        }
+    }
+
+    #[test]
+    fn runout_ends_with_the_ok_after_the_firmware_m600() {
+        let (mut serial, mut rx) = test_serial();
+        serial.handle_filament_response(r#"echo:enqueueing "M600""#);
+
+        // ok of the command which was sent before the runout
+        serial.finish_filament_change();
+        assert!(serial.filament_change.load(Ordering::Relaxed));
+
+        serial.finish_filament_change();
+        assert!(!serial.filament_change.load(Ordering::Relaxed));
+        let finished = std::iter::from_fn(|| rx.try_recv().ok())
+            .filter(|a| matches!(a, Action::Telemetry(TelemetryData::FilamentChangeFinished)))
+            .count();
+        assert_eq!(finished, 1);
+    }
+
+    #[test]
+    fn host_m600_ends_with_its_ok() {
+        let (mut serial, _rx) = test_serial();
+        serial.handle_presend_cmd("M600");
+        serial.handle_filament_response("echo:Insert filament and send M108");
+        serial.finish_filament_change();
+        assert!(!serial.filament_change.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn m0_pause_is_no_filament_change() {
+        let (mut serial, _rx) = test_serial();
+        serial.handle_filament_response("echo:busy: paused for user");
+        serial.handle_filament_response("//action:prompt_begin Click to continue");
+        assert!(!serial.filament_change.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,136 @@
+use crate::models::firmware_error::{FirmwareError, FirmwareErrorKind};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    // Error:Line Number is not Last Line Number+1, Last Line: 41 / Error:No Line Number with checksum, Last Line: 2
+    static ref RE_LINE_NUMBER: Regex = Regex::new(r"(?i)(line number is not|no line number)").unwrap();
+    // Error:checksum mismatch, Last Line: 41 / Error:No Checksum with line number, Last Line: 41
+    static ref RE_CHECKSUM: Regex = Regex::new(r"(?i)(checksum mismatch|no checksum)").unwrap();
+    // echo:Unknown command: "M999"
+    static ref RE_UNKNOWN_COMMAND: Regex = Regex::new(r"(?i)unknown command").unwrap();
+    // Error:Printer halted. kill() called! / Error:Printer stopped due to errors. Fix the error and use M999 to restart.
+    static ref RE_HALTED: Regex = Regex::new(r"(?i)(halted|kill\(\) called|printer stopped)").unwrap();
+    static ref RE_THERMAL_RUNAWAY: Regex = Regex::new(r"(?i)thermal runaway").unwrap();
+    static ref RE_MINTEMP: Regex = Regex::new(r"MINTEMP").unwrap();
+    static ref RE_MAXTEMP: Regex = Regex::new(r"MAXTEMP").unwrap();
+    static ref RE_HEATING_FAILED: Regex = Regex::new(r"(?i)heating failed").unwrap();
+    // Error:..., !! (Klipper/Repetier)
+    static ref RE_ERROR: Regex = Regex::new(r"^(Error:|!!)").unwrap();
+}
+
+/// classify a response line of the firmware, returns None if the line reports no error
+pub(crate) fn classify_error(line: &str) -> Option<FirmwareError> {
+    let is_error = RE_ERROR.is_match(line);
+    // thermal errors are also reported in echo lines before the firmware halts
+    let kind = if RE_THERMAL_RUNAWAY.is_match(line) {
+        FirmwareErrorKind::ThermalRunaway
+    } else if RE_MINTEMP.is_match(line) {
+        FirmwareErrorKind::MinTemp
+    } else if RE_MAXTEMP.is_match(line) {
+        FirmwareErrorKind::MaxTemp
+    } else if RE_UNKNOWN_COMMAND.is_match(line) {
+        FirmwareErrorKind::UnknownCommand
+    } else if !is_error {
+        return None;
+    } else if RE_HEATING_FAILED.is_match(line) {
+        FirmwareErrorKind::HeatingFailed
+    } else if RE_HALTED.is_match(line) {
+        FirmwareErrorKind::Halted
+    } else if RE_LINE_NUMBER.is_match(line) {
+        FirmwareErrorKind::LineNumber
+    } else if RE_CHECKSUM.is_match(line) {
+        FirmwareErrorKind::Checksum
+    } else {
+        FirmwareErrorKind::Other
+    };
+
+    Some(FirmwareError {
+        kind,
+        message: line.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(line: &str) -> Option<FirmwareErrorKind> {
+        classify_error(line).map(|e| e.kind)
+    }
+
+    #[test]
+    fn recoverable_errors() {
+        assert_eq!(
+            kind("Error:Line Number is not Last Line Number+1, Last Line: 41"),
+            Some(FirmwareErrorKind::LineNumber)
+        );
+        assert_eq!(
+            kind("Error:No Line Number with checksum, Last Line: 2"),
+            Some(FirmwareErrorKind::LineNumber)
+        );
+        assert_eq!(
+            kind("Error:checksum mismatch, Last Line: 41"),
+            Some(FirmwareErrorKind::Checksum)
+        );
+        assert_eq!(
+            kind("Error:No Checksum with line number, Last Line: 41"),
+            Some(FirmwareErrorKind::Checksum)
+        );
+        assert_eq!(
+            kind(r#"echo:Unknown command: "M999""#),
+            Some(FirmwareErrorKind::UnknownCommand)
+        );
+    }
+
+    #[test]
+    fn fatal_errors() {
+        assert_eq!(
+            kind("Error:Printer halted. kill() called!"),
+            Some(FirmwareErrorKind::Halted)
+        );
+        assert_eq!(
+            kind("Error:Printer stopped due to errors. Fix the error and use M999 to restart."),
+            Some(FirmwareErrorKind::Halted)
+        );
+        assert_eq!(
+            kind("Error:Thermal Runaway, system stopped! Heater_ID: 0"),
+            Some(FirmwareErrorKind::ThermalRunaway)
+        );
+        // thermal errors are reported in echo lines as well
+        assert_eq!(
+            kind("echo: MINTEMP triggered, system stopped! Heater_ID: bed"),
+            Some(FirmwareErrorKind::MinTemp)
+        );
+        assert_eq!(
+            kind("Error:MAXTEMP triggered, system stopped! Heater_ID: 0"),
+            Some(FirmwareErrorKind::MaxTemp)
+        );
+        assert_eq!(
+            kind("Error:Heating failed, system stopped! Heater_ID: 0"),
+            Some(FirmwareErrorKind::HeatingFailed)
+        );
+        assert_eq!(kind("!! Move out of range"), Some(FirmwareErrorKind::Other));
+    }
+
+    #[test]
+    fn no_errors() {
+        assert_eq!(kind("echo:busy: processing"), None);
+        assert_eq!(kind("echo:Insert filament and send M108"), None);
+        assert_eq!(kind("ok T:210.0 /210.0 B:60.0 /60.0"), None);
+    }
+
+    #[test]
+    fn fatal_kinds() {
+        let error = |kind| FirmwareError {
+            kind,
+            message: String::new(),
+        };
+        assert!(!error(FirmwareErrorKind::LineNumber).is_fatal());
+        assert!(!error(FirmwareErrorKind::Checksum).is_fatal());
+        assert!(!error(FirmwareErrorKind::UnknownCommand).is_fatal());
+        assert!(error(FirmwareErrorKind::Halted).is_fatal());
+        assert!(error(FirmwareErrorKind::NoResponse).is_fatal());
+        assert!(error(FirmwareErrorKind::Other).is_fatal());
+    }
+}
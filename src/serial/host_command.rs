@@ -0,0 +1,122 @@
+use crate::models::action::TelemetryData;
+use crate::models::job::{JobState, LayerAction, LayerHook};
+use crate::serial::event_loop::Serial;
+use log::{info, warn};
+
+/// host commands are queued like gcode lines but handled by the lib instead of being sent to the printer
+pub(crate) const HOST_CMD_PREFIX: char = '@';
+/// `@layer <index> <total> <z>` marks the start of a layer
+pub(crate) const HOST_CMD_LAYER: &str = "@layer";
+/// `@pause` pauses the print
+pub(crate) const HOST_CMD_PAUSE: &str = "@pause";
+/// `@meatpack on|off` negotiates MeatPack packing with the firmware
+pub(crate) const HOST_CMD_MEATPACK: &str = "@meatpack";
+/// `@idle extend` restarts the idle heater timeout
+pub(crate) const HOST_CMD_IDLE: &str = "@idle";
+
+impl Serial {
+    pub(crate) async fn handle_host_cmd(&mut self, cmd: &str) {
+        let mut parts = cmd.split_whitespace();
+        match parts.next() {
+            Some(HOST_CMD_LAYER) => {
+                let index: u32 = parts.next().and_then(|v| v.parse().ok()).unwrap_or(0);
+                let total: u32 = parts.next().and_then(|v| v.parse().ok()).unwrap_or(0);
+                let z: f32 = parts.next().and_then(|v| v.parse().ok()).unwrap_or(0.0);
+
+                self.job.stats.lock().unwrap().layer_reached = index + 1;
+                self.send_telemetry(TelemetryData::LayerChanged { index, total, z });
+                self.display_message(&format!("Layer {}/{}", index + 1, total), false);
+                self.run_layer_hooks(index, z);
+            }
+            Some(HOST_CMD_PAUSE) => {
+                info!("Pause requested by print file");
+                self.pause_job();
+                self.display_message("Paused by print file", true);
+            }
+            Some(HOST_CMD_MEATPACK) => {
+                self.set_meatpack(parts.next() == Some("on")).await;
+            }
+            Some(HOST_CMD_IDLE) if parts.next() == Some("extend") => {
+                self.extend_idle_timeout();
+            }
+            _ => warn!("Unknown host command: {}", cmd),
+        }
+    }
+
+    /// pause the job after the running moves finished
+    pub(crate) fn pause_job(&self) {
+        if matches!(
+            self.job.state(),
+            Some(JobState::Heating | JobState::Printing)
+        ) {
+            self.job.transition(JobState::Pausing);
+            self.que.lock().unwrap().push_front("M400".to_string());
+        }
+    }
+
+    fn run_layer_hooks(&mut self, layer: u32, z: f32) {
+        let hooks: Vec<LayerHook> = {
+            let mut hooks = self.job.hooks.lock().unwrap();
+            let (run, keep): (Vec<LayerHook>, Vec<LayerHook>) =
+                hooks.drain(..).partition(|h| h.is_triggered(layer, z));
+            *hooks = keep;
+            if !run.is_empty() {
+                self.send_telemetry(TelemetryData::LayerHooks(hooks.clone()));
+            }
+            run
+        };
+
+        // the commands of all hooks are sent before the next job line in the order of the hooks
+        let mut cmds = vec![];
+        for hook in hooks {
+            info!("Running layer hook at layer {}: {:?}", layer, hook.action);
+            match hook.action {
+                LayerAction::Pause => {
+                    self.pause_job();
+                    self.display_message(&format!("Paused at layer {}", layer + 1), true);
+                }
+                LayerAction::FilamentChange => cmds.push("M600".to_string()),
+                LayerAction::Script(lines) => cmds.extend(lines),
+            }
+        }
+        let mut que = self.que.lock().unwrap();
+        for cmd in cmds.into_iter().rev() {
+            que.push_front(cmd);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::job::{HookTrigger, LayerAction, LayerHook};
+    use crate::serial::event_loop::test_serial;
+
+    #[test]
+    fn hooks_of_a_layer_run_in_order() {
+        let (mut serial, _rx) = test_serial();
+        let hook = |action| LayerHook {
+            trigger: HookTrigger::Layer(2),
+            action,
+        };
+        *serial.job.hooks.lock().unwrap() = vec![
+            hook(LayerAction::Script(vec![
+                "M117 a".to_string(),
+                "M117 b".to_string(),
+            ])),
+            hook(LayerAction::FilamentChange),
+            hook(LayerAction::Script(vec!["M117 c".to_string()])),
+            LayerHook {
+                trigger: HookTrigger::Layer(3),
+                action: LayerAction::FilamentChange,
+            },
+        ];
+        serial.que.lock().unwrap().push_back("G1 X1".to_string());
+
+        serial.run_layer_hooks(2, 0.6);
+        assert_eq!(
+            *serial.que.lock().unwrap(),
+            ["M117 a", "M117 b", "M600", "M117 c", "G1 X1"]
+        );
+        assert_eq!(serial.job.hooks.lock().unwrap().len(), 1);
+    }
+}
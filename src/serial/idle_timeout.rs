@@ -0,0 +1,60 @@
+use crate::models::action::{PrinterStatus, TelemetryData};
+use crate::models::temperature::Heater;
+use crate::serial::event_loop::Serial;
+use log::{info, warn};
+use std::time::Instant;
+
+/// time the printer is idle with heaters on
+#[derive(Default)]
+pub(crate) struct IdleHeaters {
+    since: Option<Instant>,
+    warned: bool,
+}
+
+impl Serial {
+    /// commands other than the temperature poll are activity of the user
+    pub(crate) fn handle_idle_presend(&mut self, cmd: &str) {
+        if cmd != "M105" {
+            self.idle_heaters = IdleHeaters::default();
+        }
+    }
+
+    /// restart the idle timeout, called by the host command `@idle extend`
+    pub(crate) fn extend_idle_timeout(&mut self) {
+        info!("Idle heater timeout extended");
+        self.idle_heaters = IdleHeaters::default();
+    }
+
+    /// turn off the heaters if the printer is idle with heaters on for longer than the idle timeout
+    pub(crate) fn check_idle_heaters(&mut self) {
+        let Some(config) = *self.job.idle_timeout.lock().unwrap() else {
+            return;
+        };
+
+        let heating =
+            self.heater_target(Heater::Extruder) > 0.0 || self.heater_target(Heater::Bed) > 0.0;
+        if !heating || *self.printer_status.lock().unwrap() != PrinterStatus::Idle {
+            self.idle_heaters = IdleHeaters::default();
+            return;
+        }
+
+        let since = *self.idle_heaters.since.get_or_insert_with(Instant::now);
+        let elapsed = since.elapsed().as_secs() as u32;
+        if elapsed >= config.timeout {
+            warn!("Printer was idle for {}s, turning off the heaters", elapsed);
+            self.idle_heaters = IdleHeaters::default();
+            {
+                let mut que = self.que.lock().unwrap();
+                que.push_back("M104 S0".to_string());
+                que.push_back("M140 S0".to_string());
+                if config.disable_motors {
+                    que.push_back("M84".to_string());
+                }
+            }
+            self.send_telemetry(TelemetryData::IdleTimeoutExpired);
+        } else if elapsed + config.warning >= config.timeout && !self.idle_heaters.warned {
+            self.idle_heaters.warned = true;
+            self.send_telemetry(TelemetryData::IdleTimeoutWarning(config.timeout - elapsed));
+        }
+    }
+}
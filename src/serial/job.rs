@@ -0,0 +1,116 @@
+use crate::models::action::{Action, HostPrompt, TelemetryData};
+use crate::models::file::JobStats;
+use crate::models::job::{DisplayUpdates, JobState, LayerHook, PrintObject};
+use crate::models::profile::HeaterLimits;
+use crate::models::temperature::{IdleTimeout, ThermalWatchdog};
+use log::{debug, info};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::Sender;
+
+/// Progress of the file after a job line was sent
+#[derive(Clone, Copy, Default)]
+pub(crate) struct LinePosition {
+    /// bytes of the file read up to this line
+    pub(crate) bytes: u64,
+    /// gcode commands of the file up to this line
+    pub(crate) line: u32,
+    /// estimated print time in seconds up to this line
+    pub(crate) time: f32,
+    /// labeled object the line belongs to
+    pub(crate) object: Option<u32>,
+}
+
+/// The active print job shared between GcodeSerial and the serial event loop
+#[derive(Clone)]
+pub(crate) struct Job {
+    /// gcode lines of the job which are not sent yet
+    pub(crate) lines: Arc<Mutex<VecDeque<String>>>,
+    pub(crate) stats: Arc<Mutex<JobStats>>,
+    /// position in the file after each job line
+    pub(crate) positions: Arc<Mutex<Vec<LinePosition>>>,
+    /// minimum time between two progress updates
+    pub(crate) progress_interval: Arc<Mutex<Duration>>,
+    pub(crate) display: Arc<Mutex<DisplayUpdates>>,
+    /// checks of the reported temperatures, disabled if None
+    pub(crate) thermal_watchdog: Arc<Mutex<Option<ThermalWatchdog>>>,
+    pub(crate) heater_limits: Arc<Mutex<HeaterLimits>>,
+    /// heaters are turned off after the printer is idle for this time, disabled if None
+    pub(crate) idle_timeout: Arc<Mutex<Option<IdleTimeout>>>,
+    /// capabilities reported by the firmware with M115
+    pub(crate) capabilities: Arc<Mutex<HashMap<String, bool>>>,
+    /// labeled objects of the job
+    pub(crate) objects: Arc<Mutex<Vec<PrintObject>>>,
+    /// hooks waiting for their layer to be reached
+    pub(crate) hooks: Arc<Mutex<Vec<LayerHook>>>,
+    /// the printer prints a file of its sd card until it reports the end of the file
+    pub(crate) sd_printing: Arc<AtomicBool>,
+    /// dialog the printer shows and waits for a button to be chosen
+    pub(crate) prompt: Arc<Mutex<Option<HostPrompt>>>,
+    state: Arc<Mutex<Option<JobState>>>,
+    tx: Sender<Action>,
+}
+
+impl Job {
+    pub(crate) fn new(tx: Sender<Action>) -> Self {
+        Job {
+            lines: Arc::new(Mutex::new(VecDeque::new())),
+            stats: Arc::new(Mutex::new(JobStats::default())),
+            positions: Arc::new(Mutex::new(vec![])),
+            progress_interval: Arc::new(Mutex::new(Duration::from_secs(2))),
+            display: Arc::new(Mutex::new(DisplayUpdates::default())),
+            thermal_watchdog: Arc::new(Mutex::new(None)),
+            heater_limits: Arc::new(Mutex::new(HeaterLimits::default())),
+            idle_timeout: Arc::new(Mutex::new(None)),
+            capabilities: Arc::new(Mutex::new(HashMap::new())),
+            objects: Arc::new(Mutex::new(vec![])),
+            hooks: Arc::new(Mutex::new(vec![])),
+            sd_printing: Arc::new(AtomicBool::new(false)),
+            prompt: Arc::new(Mutex::new(None)),
+            state: Arc::new(Mutex::new(None)),
+            tx,
+        }
+    }
+
+    pub(crate) fn state(&self) -> Option<JobState> {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// a job exists and is not finished yet
+    pub(crate) fn is_active(&self) -> bool {
+        self.state().is_some_and(|s| !s.is_terminal())
+    }
+
+    /// job lines may be sent to the printer
+    pub(crate) fn is_sending(&self) -> bool {
+        matches!(
+            self.state(),
+            Some(JobState::Queued | JobState::Heating | JobState::Printing)
+        )
+    }
+
+    /// change the job state and publish the change
+    /// invalid transitions are ignored and return false
+    pub(crate) fn transition(&self, next: JobState) -> bool {
+        {
+            let mut state = self.state.lock().unwrap();
+            let allowed = match state.as_ref() {
+                None => next == JobState::Queued,
+                Some(s) => s.can_transition_to(&next),
+            };
+            if !allowed {
+                debug!("Ignoring job transition {:?} -> {:?}", state, next);
+                return false;
+            }
+            info!("Job state change: {}", next);
+            *state = Some(next.clone());
+        }
+
+        let _ = self
+            .tx
+            .send(Action::Telemetry(TelemetryData::JobStateChange(next)));
+        true
+    }
+}
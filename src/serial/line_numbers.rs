@@ -0,0 +1,117 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::VecDeque;
+
+lazy_static! {
+    // M110 N0 / M110
+    static ref RE_SET_LINE_NUMBER: Regex = Regex::new(r"^M110\b(?:.*N(\d+))?").unwrap();
+}
+
+/// sent lines kept to answer resend requests of the firmware
+const HISTORY_LEN: usize = 32;
+
+/// Line numbers and checksums of the sent lines, the firmware requests lost or corrupted lines again
+#[derive(Default)]
+pub(crate) struct LineNumbers {
+    /// number of the last sent line
+    last: u32,
+    history: VecDeque<(u32, String)>,
+    /// line the firmware requested with `Resend: N`
+    pub(crate) resend_from: Option<u32>,
+}
+
+impl LineNumbers {
+    /// add the next line number and the checksum to the command,
+    /// M110 is sent as it is and sets the number of the last line
+    pub(crate) fn number_line(&mut self, cmd: &str) -> String {
+        if let Some(c) = RE_SET_LINE_NUMBER.captures(cmd) {
+            self.last = c.get(1).and_then(|m| m.as_str().parse().ok()).unwrap_or(0);
+            self.history.clear();
+            return cmd.to_string();
+        }
+
+        self.last += 1;
+        self.history.push_back((self.last, cmd.to_string()));
+        if self.history.len() > HISTORY_LEN {
+            self.history.pop_front();
+        }
+        let line = format!("N{} {}", self.last, cmd);
+        format!("{}*{}", line, checksum(&line))
+    }
+
+    /// the lines the firmware did not accept, oldest first, from the requested line up to the last sent one.
+    /// the next line gets the number the firmware expects
+    pub(crate) fn take_resend(&mut self) -> Vec<String> {
+        let requested = self.resend_from.take().unwrap_or(self.last);
+        // the last line was rejected in any case
+        let from = requested.min(self.last);
+
+        let mut lines = VecDeque::new();
+        while self.history.back().is_some_and(|(n, _)| *n >= from) {
+            let (_, cmd) = self.history.pop_back().unwrap();
+            lines.push_front(cmd);
+        }
+        self.last = requested.saturating_sub(1);
+        lines.into()
+    }
+
+    /// the printer restarted and expects the first line again
+    pub(crate) fn reset(&mut self) {
+        *self = LineNumbers::default();
+    }
+}
+
+/// xor of all bytes of the line before the `*`
+fn checksum(line: &str) -> u8 {
+    line.bytes().fold(0, |c, b| c ^ b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbered_lines_have_checksums() {
+        let mut numbers = LineNumbers::default();
+        assert_eq!(numbers.number_line("M110 N0"), "M110 N0");
+        assert_eq!(numbers.number_line("G28"), "N1 G28*18");
+        assert_eq!(numbers.number_line("M105"), "N2 M105*37");
+    }
+
+    #[test]
+    fn set_line_number_continues_after_it() {
+        let mut numbers = LineNumbers::default();
+        numbers.number_line("G28");
+        numbers.number_line("M110 N40");
+        assert!(numbers.number_line("G1 X1").starts_with("N41 "));
+    }
+
+    #[test]
+    fn resend_requested_line() {
+        let mut numbers = LineNumbers::default();
+        for cmd in ["G28", "G1 X1", "G1 X2"] {
+            numbers.number_line(cmd);
+        }
+        numbers.resend_from = Some(2);
+        assert_eq!(numbers.take_resend(), vec!["G1 X1", "G1 X2"]);
+        assert!(numbers.number_line("G1 X1").starts_with("N2 "));
+    }
+
+    #[test]
+    fn resend_last_line_for_a_checksum_error() {
+        let mut numbers = LineNumbers::default();
+        numbers.number_line("G28");
+        numbers.number_line("G1 X1");
+        assert_eq!(numbers.take_resend(), vec!["G1 X1"]);
+        assert!(numbers.number_line("G1 X1").starts_with("N2 "));
+    }
+
+    #[test]
+    fn resend_ahead_of_the_host_renumbers_the_last_line() {
+        let mut numbers = LineNumbers::default();
+        numbers.number_line("G28");
+        numbers.resend_from = Some(5);
+        assert_eq!(numbers.take_resend(), vec!["G28"]);
+        assert!(numbers.number_line("G28").starts_with("N5 "));
+    }
+}
@@ -0,0 +1,109 @@
+use crate::gcode::meatpack::{encode_line, DISABLE, ENABLE, QUERY_STATE};
+use crate::models::action::TelemetryData;
+use crate::serial::event_loop::Serial;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use regex::Regex;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    // [MP] PV01 ON NSP
+    static ref RE_MEATPACK_STATE: Regex = Regex::new(r"\[MP\].*\b(ON|OFF)\b").unwrap();
+}
+
+/// time the firmware has to answer a MeatPack state query
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// MeatPack state of the connection and bytes saved by packing
+#[derive(Default)]
+pub(crate) struct MeatPack {
+    pub(crate) active: bool,
+    /// bytes the sent lines would have had without packing
+    bytes_plain: u64,
+    bytes_sent: u64,
+}
+
+impl Serial {
+    /// bytes written to the port for a command
+    pub(crate) fn encode_cmd(&mut self, cmd: &str) -> Vec<u8> {
+        if !self.meatpack.active {
+            return format!("{}\n", cmd).into_bytes();
+        }
+        let packed = encode_line(cmd);
+        self.meatpack.bytes_plain += cmd.len() as u64 + 1;
+        self.meatpack.bytes_sent += packed.len() as u64;
+        packed
+    }
+
+    pub(crate) fn send_meatpack_stats(&self) {
+        self.send_telemetry(TelemetryData::MeatPack {
+            active: self.meatpack.active,
+            bytes_plain: self.meatpack.bytes_plain,
+            bytes_sent: self.meatpack.bytes_sent,
+        });
+    }
+
+    /// enable packing if the firmware supports MeatPack or disable it
+    pub(crate) async fn set_meatpack(&mut self, enable: bool) {
+        if enable == self.meatpack.active {
+            return;
+        }
+
+        if !enable {
+            if let Err(e) = self.write_raw(&DISABLE) {
+                warn!("Failed to disable MeatPack: {}", e);
+            }
+            self.meatpack.active = false;
+            self.send_meatpack_stats();
+            return;
+        }
+
+        // firmware without MeatPack reads the query as an unknown command line
+        let query = [&QUERY_STATE[..], b"\n"].concat();
+        if self.query_meatpack(&query).await.is_none() {
+            warn!("Firmware does not support MeatPack");
+            self.send_meatpack_stats();
+            return;
+        }
+
+        let enable = [&ENABLE[..], &QUERY_STATE[..]].concat();
+        self.meatpack.active = self.query_meatpack(&enable).await == Some(true);
+        if self.meatpack.active {
+            info!("MeatPack packing enabled");
+        } else {
+            warn!("Firmware did not enable MeatPack");
+        }
+        self.send_meatpack_stats();
+    }
+
+    /// write the bytes and wait for the reported MeatPack state
+    async fn query_meatpack(&mut self, bytes: &[u8]) -> Option<bool> {
+        if let Err(e) = self.write_raw(bytes) {
+            warn!("Failed to query MeatPack state: {}", e);
+            return None;
+        }
+
+        let start = Instant::now();
+        let mut remainder = String::new();
+        while start.elapsed() < QUERY_TIMEOUT {
+            let response = match self.read_port() {
+                Ok(r) if !r.is_empty() => format!("{}{}", remainder, r),
+                _ => {
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    continue;
+                }
+            };
+
+            let mut lines: Vec<&str> = response.split('\n').collect();
+            remainder = lines.pop().unwrap_or("").to_string();
+            for line in lines.into_iter().map(str::trim).filter(|l| !l.is_empty()) {
+                info!("<<<{}", line);
+                if let Some(c) = RE_MEATPACK_STATE.captures(line) {
+                    return Some(&c[1] == "ON");
+                }
+                self.handle_response(line);
+            }
+        }
+        None
+    }
+}
@@ -1,4 +1,19 @@
+mod action_command;
+pub(crate) mod boot;
+pub(crate) mod display;
 pub(crate) mod event_loop;
+mod filament_change;
+mod firmware_error;
+pub(crate) mod host_command;
+mod idle_timeout;
+pub(crate) mod job;
+mod line_numbers;
+mod meatpack;
+pub(crate) mod objects;
 mod pre_send_handler;
+mod progress;
+mod response;
 mod response_handler;
+pub(crate) mod sd_card;
 mod temp_interval;
+mod thermal;
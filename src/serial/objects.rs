@@ -0,0 +1,221 @@
+use crate::serial::event_loop::Serial;
+use crate::serial::host_command::HOST_CMD_PREFIX;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    // moves and firmware retractions
+    static ref RE_MOTION: Regex = Regex::new(r"^G([0-3]|1[01])\b").unwrap();
+    static ref RE_PARAM: Regex = Regex::new(r"([XYZEF])(-?[\d.]+)").unwrap();
+    // G92 E0
+    static ref RE_E_RESET: Regex = Regex::new(r"^G92\b.*E").unwrap();
+}
+
+/// What to do with a job line regarding cancelled objects
+pub(crate) enum LineFilter {
+    Send,
+    /// the line moves within a cancelled object
+    Skip,
+    /// the commands replace the skipped moves and are sent before the line
+    SendAfter(Vec<String>),
+}
+
+/// End state of the moves skipped since the last sent move
+#[derive(Default)]
+pub(crate) struct SkippedMoves {
+    x: Option<f32>,
+    y: Option<f32>,
+    z: Option<f32>,
+    /// absolute extruder position
+    e: Option<f32>,
+    f: Option<f32>,
+}
+
+impl Serial {
+    /// check if the job line belongs to a cancelled object
+    pub(crate) fn filter_job_line(&mut self, cmd: &str) -> LineFilter {
+        if cmd.starts_with(HOST_CMD_PREFIX) {
+            return LineFilter::Send;
+        }
+
+        let cancelled = self.line_object().is_some_and(|id| {
+            self.job
+                .objects
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|o| o.id == id && o.cancelled)
+        });
+        if cancelled && RE_MOTION.is_match(cmd) {
+            self.skip_move(cmd);
+            return LineFilter::Skip;
+        }
+        // other commands of the cancelled object are sent, the moves continue afterwards
+        if cancelled {
+            // the printer counts from the reset position, the skipped extrusion is not restored
+            if RE_E_RESET.is_match(cmd) {
+                if let Some(s) = self.skipped_moves.as_mut() {
+                    s.e = None;
+                }
+            }
+            return LineFilter::Send;
+        }
+
+        match self.skipped_moves.take() {
+            None => LineFilter::Send,
+            Some(s) => LineFilter::SendAfter(Self::travel_cmds(s)),
+        }
+    }
+
+    /// object of the job line which was taken last
+    fn line_object(&self) -> Option<u32> {
+        let lines_left = self.job.lines.lock().unwrap().len();
+        let positions = self.job.positions.lock().unwrap();
+        let index = positions.len().checked_sub(lines_left + 1)?;
+        positions.get(index).and_then(|p| p.object)
+    }
+
+    fn skip_move(&mut self, cmd: &str) {
+        let modes = *self.motion_modes.lock().unwrap();
+        let s = self.skipped_moves.get_or_insert_with(SkippedMoves::default);
+        for c in RE_PARAM.captures_iter(cmd) {
+            let v: f32 = c[2].parse().unwrap_or(0.0);
+            let axis = match &c[1] {
+                "X" => &mut s.x,
+                "Y" => &mut s.y,
+                "Z" => &mut s.z,
+                "E" if modes.relative_extrusion => continue,
+                "E" => &mut s.e,
+                _ => &mut s.f,
+            };
+            // relative moves add up to one travel
+            *axis = match (modes.relative_moves && !matches!(&c[1], "E" | "F"), *axis) {
+                (true, Some(prev)) => Some(prev + v),
+                _ => Some(v),
+            };
+        }
+    }
+
+    /// travel to the end of the skipped moves without extruding and continue with their extruder position
+    fn travel_cmds(s: SkippedMoves) -> Vec<String> {
+        let mut cmds = vec![];
+        let travel: String = [('X', s.x), ('Y', s.y), ('Z', s.z)]
+            .iter()
+            .filter_map(|(axis, v)| v.map(|v| format!(" {}{}", axis, v)))
+            .collect();
+        if !travel.is_empty() {
+            cmds.push(format!("G0{}", travel));
+        }
+        if let Some(e) = s.e {
+            cmds.push(format!("G92 E{}", e));
+        }
+        if let Some(f) = s.f {
+            cmds.push(format!("G1 F{}", f));
+        }
+        cmds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::job::PrintObject;
+    use crate::serial::event_loop::test_serial;
+    use crate::serial::job::LinePosition;
+
+    /// run the lines through the object filter, the first element tells if the line belongs to the cancelled object
+    fn filter(serial: &mut Serial, lines: &[(bool, &str)]) -> Vec<Option<Vec<String>>> {
+        *serial.job.objects.lock().unwrap() = vec![PrintObject {
+            id: 0,
+            name: "cube".to_string(),
+            bounds: None,
+            cancelled: true,
+        }];
+        *serial.job.positions.lock().unwrap() = lines
+            .iter()
+            .map(|(cancelled, _)| LinePosition {
+                object: cancelled.then_some(0),
+                ..Default::default()
+            })
+            .collect();
+        *serial.job.lines.lock().unwrap() = lines.iter().map(|(_, l)| l.to_string()).collect();
+
+        let mut results = vec![];
+        loop {
+            let Some(line) = serial.job.lines.lock().unwrap().pop_front() else {
+                break;
+            };
+            results.push(match serial.filter_job_line(&line) {
+                LineFilter::Send => Some(vec![]),
+                LineFilter::Skip => None,
+                LineFilter::SendAfter(cmds) => Some(cmds),
+            });
+        }
+        results
+    }
+
+    #[test]
+    fn skipped_moves_are_replaced_by_travel() {
+        let (mut serial, _rx) = test_serial();
+        let results = filter(
+            &mut serial,
+            &[
+                (true, "G1 X10 Y10 E1.5 F1200"),
+                (true, "G1 X20 Y15 E2.5"),
+                (false, "G1 X30 Y30 E3"),
+            ],
+        );
+        assert_eq!(
+            results,
+            vec![
+                None,
+                None,
+                Some(vec![
+                    "G0 X20 Y15".to_string(),
+                    "G92 E2.5".to_string(),
+                    "G1 F1200".to_string()
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn extruder_reset_in_cancelled_object_is_kept() {
+        let (mut serial, _rx) = test_serial();
+        let results = filter(
+            &mut serial,
+            &[
+                (true, "G1 X10 Y10 E5"),
+                (true, "G92 E0"),
+                (true, "G1 X20 Y20"),
+                (false, "G1 X30 Y30 E1"),
+            ],
+        );
+        assert_eq!(
+            results,
+            vec![
+                None,
+                Some(vec![]),
+                None,
+                Some(vec!["G0 X20 Y20".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn extrusion_after_reset_is_restored() {
+        let (mut serial, _rx) = test_serial();
+        let results = filter(
+            &mut serial,
+            &[
+                (true, "G92 E0"),
+                (true, "G1 X10 Y10 E0.8"),
+                (false, "G1 X30 Y30 E1"),
+            ],
+        );
+        assert_eq!(
+            results[2],
+            Some(vec!["G0 X10 Y10".to_string(), "G92 E0.8".to_string()])
+        );
+    }
+}
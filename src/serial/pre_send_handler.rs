@@ -12,10 +12,43 @@ lazy_static! {
 
     // regex to match a fan speed change gcode
     static ref RE_FAN_SPEED: Regex = Regex::new(r".*M106\s.*S([\d.]+)*").unwrap();
+
+    // regex to match a fan turned off
+    static ref RE_FAN_OFF: Regex = Regex::new(r"^M107\b").unwrap();
+
+    // regex to match the fan index parameter of M106/M107
+    static ref RE_FAN_INDEX: Regex = Regex::new(r"\sP(\d+)").unwrap();
+
+    // regex to match a feedrate override change
+    static ref RE_SPEED_FACTOR: Regex = Regex::new(r"^M220\s.*S(\d+)").unwrap();
+
+    // regex to match a flow override change
+    static ref RE_FLOW_FACTOR: Regex = Regex::new(r"^M221\s.*S(\d+)").unwrap();
+
+    // regex to match the tool index parameter
+    static ref RE_TOOL_INDEX: Regex = Regex::new(r"\sT(\d+)").unwrap();
+
+    // regex to match a babystep
+    static ref RE_BABYSTEP: Regex = Regex::new(r"^M290\s.*Z(-?[\d.]+)").unwrap();
+
+    // regex to match positioning mode changes (G90/G91/M82/M83)
+    static ref RE_POSITIONING_MODE: Regex = Regex::new(r"^(G9[01]|M8[23])\b").unwrap();
+
+    // regex to match an extrusion move
+    static ref RE_EXTRUSION: Regex = Regex::new(r"^G[01]\s.*E(-?[\d.]+)").unwrap();
+
+    // regex to match an extruder position reset
+    static ref RE_E_RESET: Regex = Regex::new(r"^G92\s.*E(-?[\d.]+)").unwrap();
 }
 
 impl Serial {
-    pub(crate) fn handle_presend_cmd(&self, cmd: &str) {
+    pub(crate) fn handle_presend_cmd(&mut self, cmd: &str) {
+        if cmd.starts_with("M600") {
+            self.start_filament_change(None, false);
+        }
+        self.handle_thermal_presend(cmd);
+        self.handle_idle_presend(cmd);
+
         // if M104/M140 commands are sent, read target temperatures
         if cmd.contains("M104 S") {
             match RE_TARGET_TEMP.captures(cmd) {
@@ -50,6 +83,7 @@ impl Serial {
                     .unwrap_or(-1.0);
 
                 if h1 >= 0.0 {
+                    self.current_z = h1;
                     self.send_telemetry(TelemetryData::ZHeight(h1));
                 }
             }
@@ -66,9 +100,123 @@ impl Serial {
                     .unwrap_or(-1.0);
 
                 if h1 >= 0.0 {
-                    self.send_telemetry(TelemetryData::FanSpeed(h1 / 255.0));
+                    let fan = Self::fan_index(cmd);
+                    if fan == 0 {
+                        self.send_telemetry(TelemetryData::FanSpeed(h1 / 255.0));
+                    }
+                    self.send_telemetry(TelemetryData::IndexedFanSpeed(fan, h1 / 255.0));
                 }
             }
         }
+
+        if RE_FAN_OFF.is_match(cmd) {
+            let fan = Self::fan_index(cmd);
+            if fan == 0 {
+                self.send_telemetry(TelemetryData::FanSpeed(0.0));
+            }
+            self.send_telemetry(TelemetryData::IndexedFanSpeed(fan, 0.0));
+        }
+
+        // capture feedrate override change
+        if let Some(c) = RE_SPEED_FACTOR.captures(cmd) {
+            let percent: u32 = c
+                .get(1)
+                .map_or("100", |m| m.as_str())
+                .parse()
+                .unwrap_or(100);
+            self.update_speed_factor(percent);
+        }
+
+        // capture flow override change
+        if let Some(c) = RE_FLOW_FACTOR.captures(cmd) {
+            let percent: u32 = c
+                .get(1)
+                .map_or("100", |m| m.as_str())
+                .parse()
+                .unwrap_or(100);
+            let extruder: u8 = RE_TOOL_INDEX
+                .captures(cmd)
+                .and_then(|c| c.get(1))
+                .map_or("0", |m| m.as_str())
+                .parse()
+                .unwrap_or(0);
+            self.update_flow_factor(extruder, percent);
+        }
+
+        // capture babystep
+        if let Some(c) = RE_BABYSTEP.captures(cmd) {
+            let z: f32 = c.get(1).map_or("0", |m| m.as_str()).parse().unwrap_or(0.0);
+            if z != 0.0 {
+                self.babystep_z += z;
+                self.send_telemetry(TelemetryData::BabyStepZ(self.babystep_z));
+            }
+        }
+
+        // track positioning modes to be able to restore them after manual moves
+        if let Some(c) = RE_POSITIONING_MODE.captures(cmd) {
+            let mut modes = self.motion_modes.lock().unwrap();
+            let mode = c.get(1).map_or("", |m| m.as_str());
+            match mode {
+                "G90" | "G91" => {
+                    modes.relative_moves = mode == "G91";
+                    modes.relative_extrusion = modes.relative_moves;
+                    modes.moves_known = true;
+                    modes.extrusion_known = true;
+                }
+                "M82" | "M83" => {
+                    modes.relative_extrusion = mode == "M83";
+                    modes.extrusion_known = true;
+                }
+                _ => {}
+            }
+        }
+
+        // track extruded filament and printed height of the active print
+        if let Some(c) = RE_EXTRUSION.captures(cmd) {
+            let e: f32 = c.get(1).map_or("0", |m| m.as_str()).parse().unwrap_or(0.0);
+            let delta = if self.motion_modes.lock().unwrap().relative_extrusion {
+                e
+            } else {
+                let delta = e - self.e_position;
+                self.e_position = e;
+                delta
+            };
+
+            let mut stats = self.job.stats.lock().unwrap();
+            stats.filament_used += delta;
+            // only extruding moves count, so z-hops are ignored
+            if delta > 0.0 && self.current_z > stats.max_z {
+                stats.max_z = self.current_z;
+            }
+        } else if let Some(c) = RE_E_RESET.captures(cmd) {
+            self.e_position = c.get(1).map_or("0", |m| m.as_str()).parse().unwrap_or(0.0);
+        }
+    }
+
+    pub(crate) fn update_speed_factor(&mut self, percent: u32) {
+        if self.speed_factor != percent {
+            self.speed_factor = percent;
+            self.send_telemetry(TelemetryData::SpeedFactor(percent));
+        }
+    }
+
+    pub(crate) fn update_flow_factor(&mut self, extruder: u8, percent: u32) {
+        let idx = extruder as usize;
+        if self.flow_factors.len() <= idx {
+            self.flow_factors.resize(idx + 1, 100);
+        } else if self.flow_factors[idx] == percent {
+            return;
+        }
+        self.flow_factors[idx] = percent;
+        self.send_telemetry(TelemetryData::FlowFactor(extruder, percent));
+    }
+
+    fn fan_index(cmd: &str) -> u8 {
+        RE_FAN_INDEX
+            .captures(cmd)
+            .and_then(|c| c.get(1))
+            .map_or("0", |m| m.as_str())
+            .parse()
+            .unwrap_or(0)
     }
 }
@@ -0,0 +1,100 @@
+use crate::models::action::TelemetryData;
+use crate::models::job::{JobProgress, JobState};
+use crate::serial::event_loop::Serial;
+use crate::serial::job::LinePosition;
+use std::time::Instant;
+
+impl Serial {
+    /// update print time and progress after a job line was taken from the job
+    pub(crate) fn track_progress(&mut self) {
+        let printing = self.job.state() == Some(JobState::Printing);
+        let now = Instant::now();
+        if let (true, Some(tick)) = (printing, self.print_tick) {
+            self.job.stats.lock().unwrap().print_time += (now - tick).as_secs_f32();
+        }
+        self.print_tick = printing.then_some(now);
+
+        let interval = *self.job.progress_interval.lock().unwrap();
+        let last_line = self.job.lines.lock().unwrap().is_empty();
+        if last_line || self.last_progress.is_none_or(|t| t.elapsed() >= interval) {
+            self.last_progress = Some(now);
+            self.send_progress(printing);
+        }
+    }
+
+    /// the job stopped sending lines, time until it continues is no print time
+    pub(crate) fn stop_print_time(&mut self) {
+        self.print_tick = None;
+    }
+
+    fn send_progress(&mut self, printing: bool) {
+        let lines_left = self.job.lines.lock().unwrap().len();
+        let positions = self.job.positions.lock().unwrap();
+        let (Some(total), Some(done)) = (
+            positions.last().copied(),
+            positions
+                .len()
+                .checked_sub(lines_left + 1)
+                .and_then(|i| positions.get(i).copied()),
+        ) else {
+            return;
+        };
+        drop(positions);
+
+        let progress = Self::job_progress(done, total);
+        let percent = progress.percent;
+        self.send_telemetry(TelemetryData::JobProgress(progress));
+        let remaining = if printing && total.time > 0.0 {
+            Some(self.send_time_estimate(done.time, total.time))
+        } else {
+            None
+        };
+        self.update_display_progress(percent, remaining);
+        if self.meatpack.active {
+            self.send_meatpack_stats();
+        }
+    }
+
+    fn job_progress(done: LinePosition, total: LinePosition) -> JobProgress {
+        let fraction = |done: f32, total: f32| {
+            if total > 0.0 {
+                (done / total).clamp(0.0, 1.0)
+            } else {
+                0.0
+            }
+        };
+        let time_fraction = fraction(done.time, total.time);
+        let percent = if total.time > 0.0 {
+            time_fraction
+        } else {
+            fraction(done.bytes as f32, total.bytes as f32)
+        } * 100.0;
+
+        JobProgress {
+            bytes_sent: done.bytes,
+            bytes_total: total.bytes,
+            lines_sent: done.line,
+            lines_total: total.line,
+            time_fraction,
+            percent,
+        }
+    }
+
+    /// returns the remaining seconds
+    fn send_time_estimate(&self, done: f32, total: f32) -> u32 {
+        // correct the simulated time with the ratio of the real to the simulated print time so far
+        let elapsed = self.job.stats.lock().unwrap().print_time;
+        let factor = if done > 60.0 {
+            (elapsed / done).clamp(0.5, 2.0)
+        } else {
+            1.0
+        };
+
+        let remaining = ((total - done) * factor) as u32;
+        self.send_telemetry(TelemetryData::TimeEstimate {
+            elapsed: elapsed as u32,
+            remaining,
+        });
+        remaining
+    }
+}
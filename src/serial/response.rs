@@ -0,0 +1,171 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    // ok / ok N41 P15 B3 / ok T:210.0 /210.0 B:60.0 /60.0 @:0 B@:0
+    static ref RE_OK: Regex = Regex::new(r"^ok(?:\s+(.*))?$").unwrap();
+    // Resend: 41 / Resend:41 / rs N41
+    static ref RE_RESEND: Regex = Regex::new(r"^(?:Resend:|rs)\s*N?(\d+)").unwrap();
+    // echo:busy: processing / busy: paused for user
+    static ref RE_BUSY: Regex = Regex::new(r"^(?:echo:)?busy:\s*(.*)$").unwrap();
+}
+
+/// kind of a single line received from the firmware
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ResponseLine<'a> {
+    /// the last command was processed, the payload are the values after the ok
+    Ok(Option<&'a str>),
+    /// boot banner of a restarted printer
+    Start,
+    Echo(&'a str),
+    /// action commands and other comments of the firmware
+    Comment(&'a str),
+    Error(&'a str),
+    /// the firmware requests the line with this number again
+    Resend(Option<u32>),
+    /// the firmware has no commands to process
+    Wait,
+    /// the firmware is still processing a command
+    Busy(&'a str),
+    Other(&'a str),
+}
+
+impl ResponseLine<'_> {
+    /// the firmware is ready for the next command
+    pub(crate) fn is_ok(&self) -> bool {
+        matches!(self, ResponseLine::Ok(_) | ResponseLine::Start)
+    }
+}
+
+/// classify a trimmed response line of the firmware
+pub(crate) fn classify_response(line: &str) -> ResponseLine<'_> {
+    if let Some(c) = RE_OK.captures(line) {
+        return ResponseLine::Ok(
+            c.get(1)
+                .map(|m| m.as_str().trim())
+                .filter(|p| !p.is_empty()),
+        );
+    }
+    if line == "start" {
+        return ResponseLine::Start;
+    }
+    if let Some(c) = RE_BUSY.captures(line) {
+        return ResponseLine::Busy(c.get(1).map_or("", |m| m.as_str()));
+    }
+    if let Some(c) = RE_RESEND.captures(line) {
+        return ResponseLine::Resend(c.get(1).and_then(|m| m.as_str().parse().ok()));
+    }
+    if line == "wait" {
+        return ResponseLine::Wait;
+    }
+    if let Some(msg) = line.strip_prefix("echo:") {
+        return ResponseLine::Echo(msg.trim());
+    }
+    if let Some(msg) = line.strip_prefix("//") {
+        return ResponseLine::Comment(msg.trim());
+    }
+    if let Some(msg) = line
+        .strip_prefix("Error:")
+        .or_else(|| line.strip_prefix("!!"))
+    {
+        return ResponseLine::Error(msg.trim());
+    }
+
+    ResponseLine::Other(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ok() {
+        assert_eq!(classify_response("ok"), ResponseLine::Ok(None));
+        assert_eq!(
+            classify_response("ok N41 P15 B3"),
+            ResponseLine::Ok(Some("N41 P15 B3"))
+        );
+        assert_eq!(
+            classify_response("ok T:210.0 /210.0 B:60.0 /60.0 @:0 B@:0"),
+            ResponseLine::Ok(Some("T:210.0 /210.0 B:60.0 /60.0 @:0 B@:0"))
+        );
+        assert!(classify_response("ok").is_ok());
+    }
+
+    #[test]
+    fn ok_substrings() {
+        assert_eq!(
+            classify_response("echo:Unknown command: \"book\""),
+            ResponseLine::Echo("Unknown command: \"book\"")
+        );
+        assert_eq!(
+            classify_response("Bed X: 0.00 Y: 0.00 Z: 0.12 // ok"),
+            ResponseLine::Other("Bed X: 0.00 Y: 0.00 Z: 0.12 // ok")
+        );
+        assert!(!classify_response("okay").is_ok());
+        assert!(!classify_response("echo:start").is_ok());
+        assert!(!classify_response("echo:  M200 S0 D1.75").is_ok());
+    }
+
+    #[test]
+    fn start() {
+        assert_eq!(classify_response("start"), ResponseLine::Start);
+        assert!(classify_response("start").is_ok());
+        assert_eq!(
+            classify_response("starting print"),
+            ResponseLine::Other("starting print")
+        );
+    }
+
+    #[test]
+    fn echo_and_comments() {
+        assert_eq!(
+            classify_response("echo:SD card ok"),
+            ResponseLine::Echo("SD card ok")
+        );
+        assert_eq!(
+            classify_response("//action:pause"),
+            ResponseLine::Comment("action:pause")
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            classify_response("Error:checksum mismatch, Last Line: 41"),
+            ResponseLine::Error("checksum mismatch, Last Line: 41")
+        );
+        assert_eq!(
+            classify_response("!! Shutdown due to MCU error"),
+            ResponseLine::Error("Shutdown due to MCU error")
+        );
+        assert_eq!(
+            classify_response("echo:Errors: 0"),
+            ResponseLine::Echo("Errors: 0")
+        );
+    }
+
+    #[test]
+    fn resend() {
+        assert_eq!(
+            classify_response("Resend: 41"),
+            ResponseLine::Resend(Some(41))
+        );
+        assert_eq!(classify_response("Resend:7"), ResponseLine::Resend(Some(7)));
+        assert_eq!(classify_response("rs N12"), ResponseLine::Resend(Some(12)));
+    }
+
+    #[test]
+    fn wait_and_busy() {
+        assert_eq!(classify_response("wait"), ResponseLine::Wait);
+        assert_eq!(
+            classify_response("echo:busy: processing"),
+            ResponseLine::Busy("processing")
+        );
+        assert_eq!(
+            classify_response("busy: paused for user"),
+            ResponseLine::Busy("paused for user")
+        );
+        assert!(!classify_response("echo:busy: processing").is_ok());
+    }
+}
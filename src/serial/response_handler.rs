@@ -1,25 +1,33 @@
-use crate::models::action::{Action, PrinterAction, PrinterStatus, TelemetryData};
-use crate::models::temperature::Temperature;
+use crate::models::action::TelemetryData;
+use crate::models::temperature::{Heater, Temperature};
 use crate::serial::event_loop::Serial;
 use lazy_static::lazy_static;
-use log::warn;
 use regex::Regex;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 lazy_static! {
-    static ref RE_M105: Regex = Regex::new(r".*T:([\d.]+)\s\/([\d.]+)\sB:([\d.]+)\s\/([\d.]+)\s.*").unwrap();
+    // a disconnected sensor is reported below zero: T:-14.00 /0.00 B:21.30 /0.00 @:0 B@:0
+    static ref RE_M105: Regex = Regex::new(r".*T:(-?[\d.]+)\s\/([\d.]+)\sB:(-?[\d.]+)\s\/([\d.]+)\s.*").unwrap();
     // regex while heating process:
-    static ref RE_HEATING: Regex = Regex::new(r".*T:([\d.]+)\s.*B:([\d.]+).*").unwrap();
+    static ref RE_HEATING: Regex = Regex::new(r".*T:(-?[\d.]+)\s.*B:(-?[\d.]+).*").unwrap();
 
     // NORMAL MODE: Percent done: 68; print time remaining in mins: 8; Change in mins: -1
     static ref RE_SD_PRINT: Regex = Regex::new(r"NORMAL MODE: Percent done: ([\d]+); print time remaining in mins: ([\d]+); .*").unwrap();
 
-    // regex to match action commands
-    static ref RE_ACTION_COMMAND: Regex = Regex::new(r".*\/\/\s+action:(\w*).*").unwrap();
+    // regex to match action commands: //action:prompt_begin Filament runout
+    static ref RE_ACTION_COMMAND: Regex = Regex::new(r"//\s*action:(\w+)\s*(.*)").unwrap();
+
+    // Cap:ARCS:1
+    static ref RE_CAPABILITY: Regex = Regex::new(r"^Cap:(\w+):([01])").unwrap();
+
+    // FR:100%
+    static ref RE_SPEED_FACTOR: Regex = Regex::new(r"FR:(\d+)%").unwrap();
+    // echo:E0 Flow: 100%
+    static ref RE_FLOW_FACTOR: Regex = Regex::new(r"E(\d+) Flow:\s*(\d+)%").unwrap();
 }
 
 impl Serial {
-    pub(crate) fn handle_response(&self, line: &str) {
+    pub(crate) fn handle_response(&mut self, line: &str) {
         // capture a M105 temp response
         match RE_M105.captures(line) {
             None => {}
@@ -42,6 +50,9 @@ impl Serial {
                 self.send_telemetry(TelemetryData::Temps(temp.clone()));
                 self.send_telemetry(TelemetryData::TargetBedTemp(b1_t as u32));
                 self.send_telemetry(TelemetryData::TargetExtruderTemp(h1_t as u32));
+                self.set_heater_target(Heater::Extruder, h1_t);
+                self.set_heater_target(Heater::Bed, b1_t);
+                self.check_idle_heaters();
             }
         }
 
@@ -61,7 +72,10 @@ impl Serial {
                     ex_temp: h1,
                 };
 
-                self.send_telemetry(TelemetryData::Temps(temp.clone()))
+                self.send_telemetry(TelemetryData::Temps(temp.clone()));
+                // the reports of M105 also match this regex
+                self.watch_temperature(Heater::Extruder, h1);
+                self.watch_temperature(Heater::Bed, b1);
             }
         }
 
@@ -80,36 +94,73 @@ impl Serial {
         }
 
         // handle action responses
-        match RE_ACTION_COMMAND.captures(line) {
-            None => {}
-            Some(c) => {
-                let action = c.get(1).map_or("0", |m| m.as_str());
-                match action {
-                    "cancel" => {
-                        //let _ = self.p_actions.stop_print();
-                        let _ = self.tx.send(Action::PrinterAction(PrinterAction::Cancel));
-                        warn!("canceling print job");
-                    }
-                    "pause" => {
-                        // todo send correct pause command
-                        // wait for continue from printer
-                        let _ = self.tx.send(Action::PrinterAction(PrinterAction::Pause));
-                        warn!("Pause Command from Printer received");
-                    }
-                    "resume" => {
-                        // resume print - but how to get this message?
-                        let _ = self.tx.send(Action::PrinterAction(PrinterAction::Resume));
-                        warn!("Continue Command from Printer received");
-                    }
-                    v => {
-                        warn!("Unknown action Command received: {}", v);
-                    }
-                }
-            }
+        if let Some(c) = RE_ACTION_COMMAND.captures(line) {
+            self.handle_action_command(&c[1], c[2].trim());
+        }
+
+        // the firmware shows dialogs only if the host announced prompt support
+        if line.contains("Cap:PROMPT_SUPPORT:1") {
+            self.que.lock().unwrap().push_back("M876 P1".to_string());
+        }
+
+        if let Some(c) = RE_CAPABILITY.captures(line) {
+            self.job
+                .capabilities
+                .lock()
+                .unwrap()
+                .insert(c[1].to_string(), &c[2] == "1");
         }
 
-        if line.contains("Done printing file") {
-            self.update_status(PrinterStatus::Idle);
+        // feedrate and flow override reports
+        if let Some(c) = RE_SPEED_FACTOR.captures(line) {
+            let percent: u32 = c
+                .get(1)
+                .map_or("100", |m| m.as_str())
+                .parse()
+                .unwrap_or(100);
+            self.update_speed_factor(percent);
+        }
+        if let Some(c) = RE_FLOW_FACTOR.captures(line) {
+            let extruder: u8 = c.get(1).map_or("0", |m| m.as_str()).parse().unwrap_or(0);
+            let percent: u32 = c
+                .get(2)
+                .map_or("100", |m| m.as_str())
+                .parse()
+                .unwrap_or(100);
+            self.update_flow_factor(extruder, percent);
+        }
+
+        self.handle_sd_response(line);
+        self.handle_filament_response(line);
+        self.handle_boot_response(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::action::{Action, TelemetryData};
+    use crate::models::temperature::{Heater, ThermalAlarmKind, ThermalWatchdog};
+    use crate::serial::event_loop::test_serial;
+
+    #[test]
+    fn negative_temperature_raises_sensor_alarm() {
+        let (mut serial, mut rx) = test_serial();
+        *serial.job.thermal_watchdog.lock().unwrap() = Some(ThermalWatchdog::default());
+
+        serial.handle_response("ok T:-14.00 /210.00 B:21.30 /0.00 @:0 B@:0");
+
+        let mut temps = None;
+        let mut alarm = None;
+        while let Ok(action) = rx.try_recv() {
+            match action {
+                Action::Telemetry(TelemetryData::Temps(t)) => temps = Some(t),
+                Action::Telemetry(TelemetryData::ThermalAlarm(a)) => alarm = Some(a),
+                _ => {}
+            }
         }
+        assert_eq!(temps.unwrap().ex_temp, -14.0);
+        let alarm = alarm.expect("no thermal alarm");
+        assert_eq!(alarm.heater, Heater::Extruder);
+        assert_eq!(alarm.kind, ThermalAlarmKind::SensorDisconnected);
     }
 }
@@ -0,0 +1,209 @@
+use crate::models::action::{PrinterStatus, TelemetryData};
+use crate::models::sd_card::{SdFile, SdUploadProgress};
+use crate::serial::event_loop::Serial;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use regex::Regex;
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+
+lazy_static! {
+    // TEST~1.GCO 12345 test long name.gcode (long name only with M20 L)
+    static ref RE_SD_FILE: Regex = Regex::new(r"^(\S+)\s+(\d+)(?:\s+(.+))?$").unwrap();
+    // SD printing byte 1234/56789
+    static ref RE_SD_PRINT_BYTE: Regex = Regex::new(r"SD printing byte (\d+)/(\d+)").unwrap();
+    // Current file: TEST~1.GCO test long name.gcode
+    static ref RE_SD_CURRENT_FILE: Regex = Regex::new(r"Current file:\s*(\S+)(?:\s+(.+))?").unwrap();
+    // open failed, File: test.gco. / Deletion failed, File: test.gco.
+    static ref RE_SD_FAILED: Regex = Regex::new(r"^(open failed|Deletion failed|SD init fail|No SD card)").unwrap();
+}
+
+/// A file which is written to the sd card, the lines are sent before any other command
+pub(crate) struct SdUpload {
+    pub(crate) name: String,
+    lines: VecDeque<String>,
+    /// M28 was acknowledged and the file is open for writing
+    opened: bool,
+    bytes_sent: u64,
+    bytes_total: u64,
+}
+
+impl SdUpload {
+    pub(crate) fn new(name: String, lines: Vec<String>) -> Self {
+        SdUpload {
+            name,
+            bytes_total: lines.iter().map(|l| l.len() as u64 + 1).sum(),
+            lines: VecDeque::from(lines),
+            opened: false,
+            bytes_sent: 0,
+        }
+    }
+
+    /// M28 opens the file, M29 closes it after all lines are written
+    fn next_line(&self) -> String {
+        match (self.opened, self.lines.front()) {
+            (false, _) => format!("M28 {}", self.name),
+            (true, Some(line)) => line.clone(),
+            (true, None) => "M29".to_string(),
+        }
+    }
+
+    fn percent(&self) -> u64 {
+        (self.bytes_sent * 100)
+            .checked_div(self.bytes_total)
+            .unwrap_or(100)
+    }
+
+    fn progress(&self) -> SdUploadProgress {
+        SdUploadProgress {
+            name: self.name.clone(),
+            bytes_sent: self.bytes_sent,
+            bytes_total: self.bytes_total,
+        }
+    }
+}
+
+impl Serial {
+    pub(crate) fn next_upload_line(&self) -> Option<String> {
+        self.sd_upload
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|u| u.next_line())
+    }
+
+    /// the printer acknowledged the sent upload line
+    pub(crate) fn upload_line_done(&mut self) {
+        let mut upload = self.sd_upload.lock().unwrap();
+        let Some(u) = upload.as_mut() else {
+            return;
+        };
+
+        let percent = u.percent();
+        let finished = if !u.opened {
+            u.opened = true;
+            false
+        } else if let Some(line) = u.lines.pop_front() {
+            u.bytes_sent += line.len() as u64 + 1;
+            false
+        } else {
+            true
+        };
+
+        if u.percent() != percent {
+            self.send_telemetry(TelemetryData::SdUploadProgress(u.progress()));
+        }
+        if finished {
+            info!("Upload of {} to the sd card finished", u.name);
+            self.send_telemetry(TelemetryData::SdUploadFinished {
+                name: u.name.clone(),
+                error: None,
+            });
+            *upload = None;
+        }
+    }
+
+    /// the printer answered an upload line with an error, the upload is aborted
+    pub(crate) fn upload_failed(&mut self, reason: &str) {
+        let Some(u) = self.sd_upload.lock().unwrap().take() else {
+            return;
+        };
+        warn!("Upload of {} to the sd card failed: {}", u.name, reason);
+        if u.opened {
+            self.que.lock().unwrap().push_front("M29".to_string());
+        }
+        self.send_telemetry(TelemetryData::SdUploadFinished {
+            name: u.name,
+            error: Some(reason.to_string()),
+        });
+    }
+
+    pub(crate) fn handle_sd_response(&mut self, line: &str) {
+        if line.starts_with("Begin file list") {
+            self.sd_listing = Some(vec![]);
+            return;
+        }
+        if line.starts_with("End file list") {
+            let files = self.sd_listing.take().unwrap_or_default();
+            self.send_telemetry(TelemetryData::SdFiles(files));
+            return;
+        }
+        if let Some(files) = self.sd_listing.as_mut() {
+            if let Some(c) = RE_SD_FILE.captures(line) {
+                files.push(SdFile {
+                    name: c[1].to_string(),
+                    long_name: c.get(3).map(|m| m.as_str().to_string()),
+                    size: c[2].parse().unwrap_or(0),
+                });
+            }
+            return;
+        }
+
+        if let Some(c) = RE_SD_PRINT_BYTE.captures(line) {
+            let bytes_printed: u64 = c[1].parse().unwrap_or(0);
+            let bytes_total: u64 = c[2].parse().unwrap_or(0);
+            self.send_telemetry(TelemetryData::SdPrintProgress {
+                bytes_printed,
+                bytes_total,
+            });
+            if let Some(percent) = (bytes_printed * 100).checked_div(bytes_total) {
+                self.send_telemetry(TelemetryData::PercentDone(percent as u32));
+            }
+        } else if line.starts_with("Not SD printing") {
+            self.send_telemetry(TelemetryData::SdCurrentFile(None));
+            self.sd_print_finished();
+        } else if line.contains("Done printing file") {
+            // also ends prints which were started on the display of the printer
+            self.sd_print_finished();
+            self.update_status(PrinterStatus::Idle);
+        } else if let Some(c) = RE_SD_CURRENT_FILE.captures(line) {
+            let name = c.get(2).unwrap_or(c.get(1).unwrap()).as_str().to_string();
+            self.send_telemetry(TelemetryData::SdCurrentFile(Some(name)));
+        } else if RE_SD_FAILED.is_match(line) {
+            warn!("Sd card command failed: {}", line);
+            self.send_telemetry(TelemetryData::SdError(line.to_string()));
+        }
+    }
+
+    /// the sd card print started by the host ended
+    fn sd_print_finished(&mut self) {
+        if self.job.sd_printing.swap(false, Ordering::Relaxed) {
+            info!("Sd card print finished");
+            self.update_status(PrinterStatus::Idle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::action::PrinterStatus;
+    use crate::serial::event_loop::test_serial;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn sd_print_is_active_until_done() {
+        let (mut serial, _rx) = test_serial();
+        serial.job.sd_printing.store(true, Ordering::Relaxed);
+        serial.update_status(PrinterStatus::Active);
+
+        serial.handle_response("SD printing byte 100/2000");
+        assert!(serial.job.sd_printing.load(Ordering::Relaxed));
+
+        serial.handle_response("Done printing file");
+        assert!(!serial.job.sd_printing.load(Ordering::Relaxed));
+        assert!(*serial.printer_status.lock().unwrap() == PrinterStatus::Idle);
+    }
+
+    #[test]
+    fn not_sd_printing_keeps_other_prints_active() {
+        let (mut serial, _rx) = test_serial();
+        serial.update_status(PrinterStatus::Active);
+
+        serial.handle_response("Not SD printing");
+        assert!(*serial.printer_status.lock().unwrap() == PrinterStatus::Active);
+
+        serial.job.sd_printing.store(true, Ordering::Relaxed);
+        serial.handle_response("Not SD printing");
+        assert!(*serial.printer_status.lock().unwrap() == PrinterStatus::Idle);
+    }
+}
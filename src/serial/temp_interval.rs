@@ -1,6 +1,7 @@
 use crate::models::action::{Action, PrinterStatus};
 use crate::serial::event_loop::Serial;
 use log::warn;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 
 impl Serial {
@@ -9,6 +10,9 @@ impl Serial {
         let printerstatus = self.printer_status.clone();
         let event = self.event.clone();
         let tx = self.tx.clone();
+        let job = self.job.clone();
+        let sd_upload = self.sd_upload.clone();
+        let filament_change = self.filament_change.clone();
 
         tokio::spawn(async move {
             let mut alive_counter: u32 = 0;
@@ -16,11 +20,18 @@ impl Serial {
             loop {
                 tokio::time::sleep(Duration::from_secs(5)).await;
 
+                // M105 would be written into the uploaded file,
+                // a printer waiting for a filament change does not answer until it continues
+                if sd_upload.lock().unwrap().is_some() || filament_change.load(Ordering::Relaxed) {
+                    alive_counter = 0;
+                    continue;
+                }
+
                 // check if the next action isn't already a temp poll
                 // todo recheck with some unix timestamp when the last temp poll was
                 if que.lock().unwrap().front().unwrap_or(&"".to_string()) != &"M105".to_string() {
                     if *printerstatus.lock().unwrap() == PrinterStatus::Disconnected {
-                        if que.lock().unwrap().len() > 10 {
+                        if job.is_active() {
                             *printerstatus.lock().unwrap() = PrinterStatus::Active;
                             let _ = tx.send(Action::StateChange(PrinterStatus::Active));
                         } else {
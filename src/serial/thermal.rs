@@ -0,0 +1,198 @@
+use crate::models::action::{PrinterStatus, TelemetryData};
+use crate::models::job::JobState;
+use crate::models::temperature::{Heater, ThermalAlarm, ThermalAlarmKind, ThermalReaction};
+use crate::serial::event_loop::Serial;
+use lazy_static::lazy_static;
+use log::error;
+use regex::Regex;
+use std::time::Instant;
+
+lazy_static! {
+    // M104 S210 / M104S210 / M109 S210 T0 / M140 S60 / M190 S60
+    static ref RE_SET_TEMP: Regex = Regex::new(r"^M(104|109|140|190)(?:[^\d.].*)?S([\d.]+)").unwrap();
+}
+
+/// a heater counts as heated up this close to its target
+const TARGET_TOLERANCE: f32 = 3.0;
+
+/// state of a heater watched by the thermal watchdog
+#[derive(Default)]
+pub(crate) struct HeaterWatch {
+    target: f32,
+    /// the heater is heating up towards its target since
+    heating_since: Option<Instant>,
+    /// highest temperature reported while heating
+    peak: f32,
+    /// an alarm was raised and the condition did not clear yet
+    alarmed: bool,
+}
+
+impl Serial {
+    pub(crate) fn handle_thermal_presend(&mut self, cmd: &str) {
+        if let Some(c) = RE_SET_TEMP.captures(cmd) {
+            let heater = match &c[1] {
+                "104" | "109" => Heater::Extruder,
+                _ => Heater::Bed,
+            };
+            self.set_heater_target(heater, c[2].parse().unwrap_or(0.0));
+        }
+    }
+
+    pub(crate) fn heater_target(&self, heater: Heater) -> f32 {
+        self.heater_watch[heater as usize].target
+    }
+
+    pub(crate) fn set_heater_target(&mut self, heater: Heater, target: f32) {
+        let watch = &mut self.heater_watch[heater as usize];
+        if watch.target == target {
+            return;
+        }
+        *watch = HeaterWatch {
+            target,
+            heating_since: (target > 0.0).then(Instant::now),
+            peak: 0.0,
+            // a heater which is still failing does not raise the alarm again
+            alarmed: watch.alarmed,
+        };
+    }
+
+    /// check a reported temperature if the thermal watchdog is enabled
+    pub(crate) fn watch_temperature(&mut self, heater: Heater, temp: f32) {
+        let Some(config) = *self.job.thermal_watchdog.lock().unwrap() else {
+            return;
+        };
+        let limits = *self.job.heater_limits.lock().unwrap();
+        let max_temp = match heater {
+            Heater::Extruder => limits.max_extruder_temp,
+            Heater::Bed => limits.max_bed_temp,
+        };
+
+        let watch = &mut self.heater_watch[heater as usize];
+        let mut kind = None;
+        // heaters which are off or missing like the bed of some printers report 0
+        if temp <= 0.0 && watch.target > 0.0 {
+            kind = Some(ThermalAlarmKind::SensorDisconnected);
+        } else if max_temp.is_some_and(|m| temp > m) {
+            kind = Some(ThermalAlarmKind::OutOfRange);
+        } else if let Some(since) = watch.heating_since {
+            if temp >= watch.target - TARGET_TOLERANCE {
+                watch.heating_since = None;
+            } else if watch.peak - temp > config.max_drop {
+                kind = Some(ThermalAlarmKind::TemperatureDrop);
+            } else if since.elapsed().as_secs() > config.heatup_timeout as u64 {
+                kind = Some(ThermalAlarmKind::HeatupTimeout);
+            }
+            watch.peak = watch.peak.max(temp);
+        } else if watch.target > 0.0 && watch.target - temp > config.max_drop {
+            // the target was reached, a heater holding it must not fall below it
+            kind = Some(ThermalAlarmKind::TemperatureDrop);
+        }
+
+        let Some(kind) = kind else {
+            watch.alarmed = false;
+            return;
+        };
+        if watch.alarmed {
+            return;
+        }
+        watch.alarmed = true;
+
+        let alarm = ThermalAlarm {
+            heater,
+            kind,
+            temp,
+            target: watch.target,
+        };
+        self.handle_thermal_alarm(alarm, config.reaction);
+    }
+
+    fn handle_thermal_alarm(&mut self, alarm: ThermalAlarm, reaction: ThermalReaction) {
+        let reason = format!("{} {} at {}°C", alarm.heater, alarm.kind, alarm.temp);
+        error!("Thermal watchdog: {}", reason);
+        self.send_telemetry(TelemetryData::ThermalAlarm(alarm));
+
+        self.que.lock().unwrap().clear();
+        self.job.lines.lock().unwrap().clear();
+        self.update_status(PrinterStatus::Errored);
+        if self.job.is_active() {
+            self.job.transition(JobState::Failed { reason });
+        }
+
+        let mut que = self.que.lock().unwrap();
+        match reaction {
+            // M108 ends a running M109/M190 which would block the following commands
+            ThermalReaction::HeatersOff => {
+                que.push_back("M108".to_string());
+                que.push_back("M104 S0".to_string());
+                que.push_back("M140 S0".to_string());
+            }
+            ThermalReaction::EmergencyStop => que.push_back("M112".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::action::{Action, TelemetryData};
+    use crate::models::temperature::{Heater, ThermalAlarmKind, ThermalWatchdog};
+    use crate::serial::event_loop::{test_serial, Serial};
+    use tokio::sync::broadcast::Receiver;
+
+    fn watched_serial() -> (Serial, Receiver<Action>) {
+        let (serial, rx) = test_serial();
+        *serial.job.thermal_watchdog.lock().unwrap() = Some(ThermalWatchdog::default());
+        (serial, rx)
+    }
+
+    fn alarms(rx: &mut Receiver<Action>) -> Vec<(Heater, ThermalAlarmKind)> {
+        let mut alarms = vec![];
+        while let Ok(action) = rx.try_recv() {
+            if let Action::Telemetry(TelemetryData::ThermalAlarm(a)) = action {
+                alarms.push((a.heater, a.kind));
+            }
+        }
+        alarms
+    }
+
+    #[test]
+    fn drop_at_hold_temperature_raises_alarm() {
+        let (mut serial, mut rx) = watched_serial();
+        serial.set_heater_target(Heater::Extruder, 210.0);
+        for temp in [150.0, 208.0, 211.0, 207.0] {
+            serial.watch_temperature(Heater::Extruder, temp);
+        }
+        assert!(alarms(&mut rx).is_empty());
+
+        serial.watch_temperature(Heater::Extruder, 200.0);
+        assert_eq!(
+            alarms(&mut rx),
+            vec![(Heater::Extruder, ThermalAlarmKind::TemperatureDrop)]
+        );
+    }
+
+    #[test]
+    fn lowered_target_is_no_drop() {
+        let (mut serial, mut rx) = watched_serial();
+        serial.set_heater_target(Heater::Extruder, 210.0);
+        serial.watch_temperature(Heater::Extruder, 210.0);
+        serial.set_heater_target(Heater::Extruder, 0.0);
+        serial.watch_temperature(Heater::Extruder, 150.0);
+        serial.set_heater_target(Heater::Extruder, 180.0);
+        serial.watch_temperature(Heater::Extruder, 195.0);
+        assert!(alarms(&mut rx).is_empty());
+    }
+
+    #[test]
+    fn missing_bed_is_no_disconnected_sensor() {
+        let (mut serial, mut rx) = watched_serial();
+        serial.watch_temperature(Heater::Bed, 0.0);
+        assert!(alarms(&mut rx).is_empty());
+
+        serial.set_heater_target(Heater::Bed, 60.0);
+        serial.watch_temperature(Heater::Bed, 0.0);
+        assert_eq!(
+            alarms(&mut rx),
+            vec![(Heater::Bed, ThermalAlarmKind::SensorDisconnected)]
+        );
+    }
+}